@@ -0,0 +1,12 @@
+//! Feed arbitrary bytes to the init_data parser: any input must produce a
+//! parsed document or a clean error, never a panic.
+//!
+//! Run with `cargo +nightly fuzz run initdata` from the crate directory.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = kbs_local_provider::initdata::parse_bytes(data);
+});
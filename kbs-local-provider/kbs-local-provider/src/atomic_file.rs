@@ -0,0 +1,74 @@
+use anyhow::{Context, Result};
+use nix::fcntl::OFlag;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+
+/// Write `contents` to a temporary file with `mode` and rename it into
+/// place, so readers never see a partial document.
+///
+/// The file is created with `mode` rather than chmod'ed afterwards, so a
+/// restrictive mode (e.g. 0600 for secrets) holds from the first byte.
+///
+/// The temporary name is predictable, so it is created exclusively and
+/// without following symlinks: whatever already sits there (a planted
+/// symlink, a file another process holds open) is never written through.
+/// A leftover from an interrupted run is unlinked, which only ever removes
+/// the directory entry, and creation is retried once.
+pub fn write(path: &Path, contents: &[u8], mode: u32) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = Path::new(&tmp);
+
+    let mut file = create_exclusive(tmp, mode)?;
+    // The creation mode is subject to the umask; set it exactly.
+    file.set_permissions(fs::Permissions::from_mode(mode))
+        .and_then(|()| file.write_all(contents))
+        .and_then(|()| file.sync_all())
+        .with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(tmp, path)
+        .with_context(|| format!("failed to move {} into place", path.display()))
+}
+
+fn create_exclusive(tmp: &Path, mode: u32) -> Result<fs::File> {
+    let open = || {
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .custom_flags(OFlag::O_NOFOLLOW.bits())
+            .mode(mode)
+            .open(tmp)
+    };
+    let file = match open() {
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            log::warn!("removing leftover {}", tmp.display());
+            fs::remove_file(tmp).with_context(|| format!("failed to remove {}", tmp.display()))?;
+            open()
+        }
+        file => file,
+    };
+    file.with_context(|| format!("failed to create {}", tmp.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn planted_symlink_at_the_temporary_name_is_not_followed() {
+        let dir = std::env::temp_dir().join(format!("atomic-file-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("target");
+        fs::write(&target, b"untouched").unwrap();
+        std::os::unix::fs::symlink(&target, dir.join("credential.tmp")).unwrap();
+        let path = dir.join("credential");
+
+        write(&path, b"secret", 0o600).unwrap();
+
+        assert_eq!(fs::read(&target).unwrap(), b"untouched");
+        assert_eq!(fs::read(&path).unwrap(), b"secret");
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+use anyhow::Result;
+use kbs_local_provider::resources::{self, Format, ServedKey};
+use std::path::Path;
+
+#[cfg(feature = "backup")]
+const PASSPHRASE_FILE_ENV: &str = "KBS_BACKUP_PASSPHRASE_FILE";
+#[cfg(feature = "backup")]
+const ARGON2_ENV: &str = "KBS_BACKUP_ARGON2";
+/// Argon2id cost when `KBS_BACKUP_ARGON2` is unset: 64 MiB, 3 passes.
+#[cfg(feature = "backup")]
+const DEFAULT_ARGON2: provider::crypto::Argon2Params = provider::crypto::Argon2Params {
+    memory_kib: 64 * 1024,
+    iterations: 3,
+    parallelism: 1,
+};
+
+/// Write the resources payload (the JSON document the FIFO would serve)
+/// to `path`, encrypted under the passphrase in `KBS_BACKUP_PASSPHRASE_FILE`,
+/// for `--backup`.
+///
+/// This deliberately weakens the security model: the keys are meant to
+/// exist only inside this TEE, re-derived from its TPM, but a backup opens
+/// anywhere with the passphrase, no TPM or attestation needed. It is for
+/// deployments that cannot afford to lose the identity with the TPM; keep
+/// the file and the passphrase offline and apart. See [`provider::backup`]
+/// for the format (Argon2id, cost from `KBS_BACKUP_ARGON2`, then
+/// XChaCha20-Poly1305).
+pub fn write(path: &Path, keys: &[ServedKey]) -> Result<()> {
+    let payload = resources::render(keys, Format::Json);
+    seal_to(path, &payload)
+}
+
+#[cfg(feature = "backup")]
+fn seal_to(path: &Path, payload: &[u8]) -> Result<()> {
+    use anyhow::{Context, bail};
+    use provider::SecretBytes;
+
+    let passphrase_file = std::env::var(PASSPHRASE_FILE_ENV)
+        .with_context(|| format!("--backup needs the passphrase in {PASSPHRASE_FILE_ENV}"))?;
+    let mut passphrase = SecretBytes::new(
+        std::fs::read(&passphrase_file)
+            .with_context(|| format!("failed to read {PASSPHRASE_FILE_ENV} {passphrase_file}"))?,
+    );
+    while passphrase.last().is_some_and(|b| matches!(b, b'\n' | b'\r')) {
+        passphrase.pop();
+    }
+    if passphrase.is_empty() {
+        bail!("{PASSPHRASE_FILE_ENV} {passphrase_file} is empty");
+    }
+    let params = match std::env::var(ARGON2_ENV) {
+        Ok(value) => value.parse().with_context(|| format!("invalid {ARGON2_ENV}"))?,
+        Err(_) => DEFAULT_ARGON2,
+    };
+
+    log::warn!(
+        target: provider::AUDIT_TARGET,
+        "writing a passphrase-encrypted backup of the derived keys to {}: anyone with it \
+         and the passphrase has the keys, without this TEE",
+        path.display()
+    );
+    let blob = provider::backup::seal(payload, &passphrase, params)?;
+    crate::atomic_file::write(path, &blob, 0o600)?;
+    log::info!("wrote encrypted backup to {} ({params})", path.display());
+    Ok(())
+}
+
+#[cfg(not(feature = "backup"))]
+fn seal_to(_: &Path, _: &[u8]) -> Result<()> {
+    anyhow::bail!("--backup requires kbs-local-provider built with the backup feature")
+}
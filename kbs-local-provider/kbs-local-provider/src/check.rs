@@ -0,0 +1,65 @@
+use anyhow::{Result, bail};
+
+use kbs_local_provider::initdata;
+
+/// Validate the pipeline without serving: init_data parses, a provider is
+/// detected and a derivation succeeds.
+///
+/// Prints one PASS/FAIL line per stage to stdout (later stages are SKIPped
+/// once one fails) and errors if any stage failed, so the exit status can
+/// back a liveness probe. Derived material is dropped unprinted.
+pub fn run() -> Result<()> {
+    let parsed = stage("init_data", || {
+        let mut parsed = initdata::parse()?;
+        crate::measured::apply(&mut parsed)?;
+        Ok(parsed)
+    });
+    let provider = stage_after(parsed.as_ref(), "provider", |parsed| {
+        crate::bind_tpm_hmac(provider::detect_provider()?, &parsed.init_data_digest)
+    });
+    let derived = stage_after(provider.as_ref().zip(parsed.as_ref()), "derive", |(p, parsed)| {
+        let ikm = p.ikm()?;
+        parsed.check_ikm_length(&ikm, p.kind())?;
+        let ikm = crate::stretch::apply(ikm, parsed)?;
+        let salt = crate::salt::resolve(&parsed.init_data_digest)?;
+        let namespace = crate::namespace::from_env()?;
+        for index in 0..parsed.keys.len() {
+            provider::crypto::derive_key(
+                parsed.kdf,
+                &ikm,
+                salt.as_deref().map(Vec::as_slice),
+                &namespace.info(parsed, index),
+                parsed.key_length,
+            )?;
+        }
+        Ok(())
+    });
+
+    if derived.is_none() {
+        bail!("health check failed");
+    }
+    Ok(())
+}
+
+fn stage<T>(name: &str, f: impl FnOnce() -> Result<T>) -> Option<T> {
+    match f() {
+        Ok(value) => {
+            println!("PASS {name}");
+            Some(value)
+        }
+        Err(err) => {
+            println!("FAIL {name}: {err:#}");
+            None
+        }
+    }
+}
+
+fn stage_after<P, T>(prev: Option<P>, name: &str, f: impl FnOnce(P) -> Result<T>) -> Option<T> {
+    match prev {
+        Some(prev) => stage(name, || f(prev)),
+        None => {
+            println!("SKIP {name}");
+            None
+        }
+    }
+}
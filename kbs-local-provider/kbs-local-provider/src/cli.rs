@@ -0,0 +1,90 @@
+use anyhow::{Context, Result, bail};
+
+use std::path::PathBuf;
+
+use crate::manifest::ManifestFormat;
+
+const REQUIRE_TEE_ENV: &str = "KBS_REQUIRE_TEE";
+const DEFAULT_SELFTEST_ITERATIONS: u32 = 10;
+
+/// Command-line options. Everything else is configured via init_data and env.
+#[derive(Default)]
+pub struct Args {
+    /// Print the public key manifest and exit instead of serving.
+    pub manifest: bool,
+    /// Format of the `--manifest` output.
+    pub manifest_format: ManifestFormat,
+    /// Write the (non-secret) derivation parameters here and exit.
+    pub export_params: Option<PathBuf>,
+    /// Print the derivation scheme version and parameters and exit.
+    pub scheme: bool,
+    /// Write a passphrase-encrypted backup of the derived keys here and exit.
+    pub backup: Option<PathBuf>,
+    /// Run the health check and exit instead of serving.
+    pub check: bool,
+    /// Run the provider self-test with this many IKM reads and exit.
+    pub provider_selftest: Option<u32>,
+    /// Provision the TPM AK first if it is missing (what attestation-agent-init
+    /// does), so a single process can provision, derive and serve.
+    pub provision_if_needed: bool,
+    /// Refuse to run unless the provider is hardware-rooted and the machine
+    /// has a TEE or TPM device (also `KBS_REQUIRE_TEE=1`).
+    pub require_tee: bool,
+}
+
+pub fn parse() -> Result<Args> {
+    let mut args = Args {
+        require_tee: std::env::var(REQUIRE_TEE_ENV).as_deref() == Ok("1"),
+        ..Args::default()
+    };
+    let mut iterations = None;
+    let mut argv = std::env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        if let Some(format) = arg.strip_prefix("--manifest-format=") {
+            args.manifest_format = format.parse().context("invalid --manifest-format")?;
+            continue;
+        }
+        if let Some(path) = arg.strip_prefix("--export-params=") {
+            args.export_params = Some(PathBuf::from(path));
+            continue;
+        }
+        if let Some(path) = arg.strip_prefix("--backup=") {
+            args.backup = Some(PathBuf::from(path));
+            continue;
+        }
+        match arg.as_str() {
+            "--manifest" => args.manifest = true,
+            "--scheme" => args.scheme = true,
+            "--require-tee" => args.require_tee = true,
+            "--provision-if-needed" => args.provision_if_needed = true,
+            "check" => args.check = true,
+            "provider-selftest" => args.provider_selftest = Some(DEFAULT_SELFTEST_ITERATIONS),
+            "--backup" => {
+                let path = argv.next().context("--backup needs a path")?;
+                args.backup = Some(PathBuf::from(path));
+            }
+            "--iterations" => {
+                let n = argv.next().context("--iterations needs a value")?;
+                let n: u32 = n.parse().with_context(|| format!("invalid --iterations {n:?}"))?;
+                if n == 0 {
+                    bail!("--iterations must be at least 1");
+                }
+                iterations = Some(n);
+            }
+            _ => bail!(
+                "unknown argument {arg:?} (usage: kbs-local-provider [--require-tee] \
+                 [--provision-if-needed] \
+                 [check | provider-selftest [--iterations N] \
+                 | --manifest [--manifest-format=json|jsonl] | --export-params=PATH \
+                 | --backup PATH | --scheme])"
+            ),
+        }
+    }
+    if let Some(n) = iterations {
+        let Some(selftest) = &mut args.provider_selftest else {
+            bail!("--iterations only applies to provider-selftest");
+        };
+        *selftest = n;
+    }
+    Ok(args)
+}
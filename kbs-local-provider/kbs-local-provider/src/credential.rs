@@ -0,0 +1,32 @@
+use anyhow::{Context, Result};
+use kbs_local_provider::resources::{self, Format, ServedKey};
+use std::path::Path;
+
+use crate::atomic_file;
+
+const CREDENTIALS_DIRECTORY_ENV: &str = "CREDENTIALS_DIRECTORY";
+const CREDENTIAL_NAME_ENV: &str = "KBS_CREDENTIAL_NAME";
+const DEFAULT_CREDENTIAL_NAME: &str = "kbs-resources.json";
+
+/// Write the resources payload as a systemd credential and return (one-shot).
+///
+/// The file is `$CREDENTIALS_DIRECTORY/<KBS_CREDENTIAL_NAME>` (default
+/// `kbs-resources.json`), written atomically with mode 0600. The in-memory
+/// payload is zeroized once written.
+pub fn write(keys: &[ServedKey], format: Format) -> Result<()> {
+    let dir = std::env::var(CREDENTIALS_DIRECTORY_ENV)
+        .with_context(|| format!("{CREDENTIALS_DIRECTORY_ENV} is not set; not run by systemd?"))?;
+    let name = std::env::var(CREDENTIAL_NAME_ENV)
+        .unwrap_or_else(|_| DEFAULT_CREDENTIAL_NAME.to_string());
+    let path = Path::new(&dir).join(name);
+
+    let payload = resources::render(keys, format);
+    atomic_file::write(&path, &payload, 0o600)?;
+
+    log::info!(
+        target: provider::AUDIT_TARGET,
+        "wrote CDH resources as systemd credential {}",
+        path.display()
+    );
+    Ok(())
+}
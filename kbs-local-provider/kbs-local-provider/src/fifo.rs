@@ -1,48 +1,415 @@
-use anyhow::{Context, Result};
-use base64::Engine;
-use base64::engine::general_purpose::STANDARD as B64;
-use nix::sys::stat::Mode;
-use nix::unistd::mkfifo;
+use anyhow::{Context, Result, bail};
+use nix::errno::Errno;
+use nix::fcntl::OFlag;
+use nix::sys::signal::{SaFlags, SigAction, SigHandler, SigSet, Signal, sigaction};
+use nix::sys::stat::{Mode, fchmod};
+use nix::unistd::{geteuid, mkfifo};
 use std::fs;
-use std::io::Write;
-use std::path::Path;
-use zeroize::Zeroizing;
+use std::io::{ErrorKind, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use kbs_local_provider::owner;
+use provider::AUDIT_TARGET;
 
 const CDH_RESOURCES_PATH: &str = "/etc/aa-offline_fs_kbc-resources.json";
+const FIFO_PATHS_ENV: &str = "KBS_FIFO_PATHS";
+const FIFO_MODE_ENV: &str = "KBS_FIFO_MODE";
+const REARM_ON_SIGHUP_ENV: &str = "KBS_FIFO_REARM_ON_SIGHUP";
+const SERVE_COUNT_ENV: &str = "KBS_SERVE_COUNT";
+/// Default FIFO mode: 0644, so a KBC running as another user can read it.
+const DEFAULT_FIFO_MODE: u32 = 0o644;
+/// Bits `KBS_FIFO_MODE` may set: read for anyone, write only for us.
+const ALLOWED_FIFO_MODE: u32 = 0o644;
+/// How often a stopping writer blocked in `open` is poked awake.
+const WAKE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a writer waits for its reader to drain the pipe.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the unread bytes in the pipe are checked while draining.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// SIGHUPs received so far; each one re-arms every FIFO once.
+static HANGUPS: AtomicU64 = AtomicU64::new(0);
+
+extern "C" fn on_sighup(_: nix::libc::c_int) {
+    HANGUPS.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Settings and state shared by the writer threads of one [`serve`].
+struct ServeOptions {
+    mode: Mode,
+    rearm_on_sighup: bool,
+    /// Reads to serve, across all FIFOs, before returning; `None` is unlimited.
+    count: Option<u64>,
+    served: AtomicU64,
+    stop: AtomicBool,
+}
+
+impl ServeOptions {
+    /// Reserve one of the `count` serves for a connected reader, so that
+    /// concurrent writers never serve more than `count` in total.
+    fn claim(&self) -> bool {
+        let Some(count) = self.count else {
+            return true;
+        };
+        self.served
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n < count).then_some(n + 1))
+            .is_ok()
+    }
+
+    fn exhausted(&self) -> bool {
+        self.count.is_some_and(|count| self.served.load(Ordering::SeqCst) >= count)
+    }
+}
+
+/// `KBS_SERVE_COUNT`: how many reads to serve before returning. Unset or 0
+/// means serve forever.
+fn count_from_env() -> Result<Option<u64>> {
+    let Ok(value) = std::env::var(SERVE_COUNT_ENV) else {
+        return Ok(None);
+    };
+    let count: u64 = value
+        .parse()
+        .with_context(|| format!("{SERVE_COUNT_ENV} {value:?} is not a number"))?;
+    Ok((count > 0).then_some(count))
+}
+
+/// The FIFO mode from `KBS_FIFO_MODE` (octal, e.g. `0640`), default 0644.
+///
+/// Only read bits for group and others are allowed: nobody else may write
+/// into the seed channel.
+fn mode_from_env() -> Result<Mode> {
+    let bits = match std::env::var(FIFO_MODE_ENV) {
+        Ok(mode) => u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+            .with_context(|| format!("{FIFO_MODE_ENV} {mode:?} is not an octal mode"))?,
+        Err(_) => DEFAULT_FIFO_MODE,
+    };
+    if bits & !ALLOWED_FIFO_MODE != 0 {
+        bail!("{FIFO_MODE_ENV} {bits:04o} sets bits outside {ALLOWED_FIFO_MODE:04o}");
+    }
+    Ok(Mode::from_bits_truncate(bits))
+}
 
-fn create_fifo(path: &Path, mode: Mode) -> Result<()> {
-    if path.exists() {
+/// Identity of the FIFO node we created, to detect it being replaced.
+#[derive(PartialEq, Eq)]
+struct NodeId {
+    dev: u64,
+    ino: u64,
+}
+
+/// Create the FIFO at `path`, replacing a stale node but refusing a symlink,
+/// which could point the seeds' write somewhere else.
+///
+/// With `KBS_CHECK_OWNER=1` the directory holding the FIFO must have the
+/// expected owner, so a co-tenant cannot have pre-created it.
+fn create_fifo(path: &Path, mode: Mode) -> Result<NodeId> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let parent_meta = fs::metadata(parent)
+        .with_context(|| format!("failed to stat FIFO directory {}", parent.display()))?;
+    owner::check(&parent_meta, "FIFO directory", parent)?;
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        if meta.file_type().is_symlink() {
+            log::error!("FIFO path {} is a symlink; refusing to serve on it", path.display());
+            bail!("FIFO path {} is a symlink", path.display());
+        }
         fs::remove_file(path)
             .with_context(|| format!("failed to remove stale FIFO {}", path.display()))?;
     }
     mkfifo(path, mode)
-        .with_context(|| format!("failed to create FIFO at {}", path.display()))
+        .with_context(|| format!("failed to create FIFO at {}", path.display()))?;
+
+    // mkfifo's mode went through the inherited umask; set it explicitly on
+    // the node and check the result (and owner) before any seed goes out.
+    // The read end opens without blocking and is closed again right away,
+    // so it never stands in for a real reader.
+    let fifo = fs::OpenOptions::new()
+        .read(true)
+        .custom_flags((OFlag::O_NONBLOCK | OFlag::O_NOFOLLOW).bits())
+        .open(path)
+        .with_context(|| format!("failed to open FIFO {} to set its mode", path.display()))?;
+    fchmod(fifo.as_raw_fd(), mode)
+        .with_context(|| format!("failed to set the mode of FIFO {}", path.display()))?;
+    let meta = fifo
+        .metadata()
+        .with_context(|| format!("failed to stat FIFO {}", path.display()))?;
+    drop(fifo);
+
+    let actual = meta.mode() & 0o7777;
+    if actual & !mode.bits() != 0 {
+        bail!(
+            "FIFO {} has mode {actual:04o}, more permissive than {:04o}",
+            path.display(),
+            mode.bits()
+        );
+    }
+    if meta.uid() != geteuid().as_raw() {
+        bail!("FIFO {} is owned by uid {}, not by us", path.display(), meta.uid());
+    }
+    Ok(NodeId {
+        dev: meta.dev(),
+        ino: meta.ino(),
+    })
 }
 
-/// Create a FIFO at the offline_fs_kbc resources path and serve the Ed25519
-/// seed as JSON with base64-encoded value. Loops forever so CDH can reconnect
-/// on restart.
-pub fn serve(seed: &Zeroizing<[u8; 32]>) -> Result<()> {
-    let encoded = B64.encode(seed.as_ref());
-    let json = format!("{{\"default/key/1\": \"{encoded}\"}}\n");
+/// FIFO paths to serve: the comma-separated `KBS_FIFO_PATHS`, or the
+/// offline_fs_kbc resources path.
+pub fn paths_from_env() -> Result<Vec<PathBuf>> {
+    let Ok(list) = std::env::var(FIFO_PATHS_ENV) else {
+        return Ok(vec![PathBuf::from(CDH_RESOURCES_PATH)]);
+    };
 
-    let path = Path::new(CDH_RESOURCES_PATH);
-    let mode = Mode::S_IRUSR | Mode::S_IWUSR | Mode::S_IRGRP | Mode::S_IROTH;
-    log::info!("serving CDH resources on FIFO {}", path.display());
+    let mut paths = Vec::new();
+    for entry in list.split(',') {
+        let path = PathBuf::from(entry.trim());
+        if path.as_os_str().is_empty() {
+            bail!("{FIFO_PATHS_ENV} contains an empty entry");
+        }
+        if paths.contains(&path) {
+            bail!("{FIFO_PATHS_ENV} lists {} more than once", path.display());
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Serve the resources payload written by `payload` on a FIFO at each of
+/// `paths`, with one writer thread per path, so several KBCs can read their
+/// own copy. Loops forever so each KBC can reconnect on restart.
+///
+/// `payload` is called with the FIFO's path and the opened FIFO for every
+/// reader, so it may differ per path, and a re-derived seed is served from
+/// the next read on. It streams the payload into the FIFO, so no more than
+/// it buffers itself is ever held in memory, and may derive the seeds on
+/// the spot so none are kept between readers.
+///
+/// A failed write leaves the reader with a truncated payload; the FIFO is
+/// removed so the reader sees EOF rather than a resumed stream.
+///
+/// With `KBS_FIFO_REARM_ON_SIGHUP=1` each FIFO is instead removed after
+/// its first reader and only recreated once the process gets a SIGHUP, for
+/// KBCs that read exactly once per lifecycle event; the secret-carrying
+/// node is then absent from the filesystem in between.
+///
+/// With `KBS_SERVE_COUNT=N` (N > 0) `serve` returns `Ok(())` once N reads
+/// have been served in total across all FIFOs, e.g. one per known consumer,
+/// so the caller can drop (and zeroize) the seeds.
+///
+/// If any writer fails, the others are stopped and their FIFOs removed
+/// before the first error is returned.
+pub fn serve(
+    paths: &[PathBuf],
+    payload: impl Fn(&Path, &mut dyn Write) -> Result<()> + Sync,
+) -> Result<()> {
+    let options = ServeOptions {
+        mode: mode_from_env()?,
+        rearm_on_sighup: std::env::var(REARM_ON_SIGHUP_ENV).as_deref() == Ok("1"),
+        count: count_from_env()?,
+        served: AtomicU64::new(0),
+        stop: AtomicBool::new(false),
+    };
+    if options.rearm_on_sighup {
+        let action = SigAction::new(
+            SigHandler::Handler(on_sighup),
+            SaFlags::SA_RESTART,
+            SigSet::empty(),
+        );
+        // SAFETY: the handler only increments an atomic, which is
+        // async-signal-safe.
+        unsafe { sigaction(Signal::SIGHUP, &action) }.context("failed to install SIGHUP handler")?;
+    }
+    let (done_tx, done_rx) = mpsc::channel();
 
+    thread::scope(|scope| {
+        let writers: Vec<_> = paths
+            .iter()
+            .map(|path| {
+                let done_tx = done_tx.clone();
+                let (payload, options) = (&payload, &options);
+                scope.spawn(move || {
+                    let result = serve_path(path, options, payload);
+                    done_tx.send(()).ok();
+                    result
+                })
+            })
+            .collect();
+        drop(done_tx);
+
+        // Writers only return on failure, once the serve count is reached, or
+        // when stopped after either.
+        done_rx.recv().ok();
+        options.stop.store(true, Ordering::SeqCst);
+        for (path, writer) in paths.iter().zip(&writers) {
+            while !writer.is_finished() {
+                wake(path);
+                thread::sleep(WAKE_INTERVAL);
+            }
+        }
+
+        let mut first_error = None;
+        for writer in writers {
+            let result = writer.join().expect("FIFO writer panicked");
+            if let Err(e) = result {
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    })
+}
+
+/// Wait (up to `DRAIN_TIMEOUT`) until the reader has consumed everything
+/// written to the pipe, or has closed it.
+///
+/// `write_all` returns once the tail of a payload fits in the pipe buffer,
+/// not once it has been read. The kernel would keep the tail for the reader
+/// across our close and unlink, but only a drained pipe confirms delivery
+/// before it is logged as served and counted against `KBS_SERVE_COUNT`.
+fn wait_drained(file: &fs::File, path: &Path) {
+    let started = std::time::Instant::now();
     loop {
-        create_fifo(path, mode)?;
+        let mut unread: nix::libc::c_int = 0;
+        // SAFETY: FIONREAD writes one c_int to the pointer, which is valid
+        // for the duration of the call; the fd is open for as long as `file`.
+        let rc = unsafe { nix::libc::ioctl(file.as_raw_fd(), nix::libc::FIONREAD, &mut unread) };
+        if rc != 0 || unread == 0 || reader_closed(file) {
+            return;
+        }
+        if started.elapsed() >= DRAIN_TIMEOUT {
+            log::warn!(
+                "reader on FIFO {} left {unread} bytes unread after {DRAIN_TIMEOUT:?}",
+                path.display()
+            );
+            return;
+        }
+        thread::sleep(DRAIN_POLL_INTERVAL);
+    }
+}
+
+/// Whether the read end of the pipe behind `file` has been closed.
+fn reader_closed(file: &fs::File) -> bool {
+    let mut pollfd = nix::libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: 0,
+        revents: 0,
+    };
+    // SAFETY: one valid pollfd, no timeout; POLLERR is reported on a pipe's
+    // write end once no reader is left.
+    let rc = unsafe { nix::libc::poll(&mut pollfd, 1, 0) };
+    rc > 0 && pollfd.revents & nix::libc::POLLERR != 0
+}
 
-        let mut file = fs::OpenOptions::new()
+/// Unblock a writer waiting in `open` by briefly opening the read end.
+fn wake(path: &Path) {
+    fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(OFlag::O_NONBLOCK.bits())
+        .open(path)
+        .ok();
+}
+
+fn serve_path(
+    path: &Path,
+    options: &ServeOptions,
+    payload: &impl Fn(&Path, &mut dyn Write) -> Result<()>,
+) -> Result<()> {
+    let stop = &options.stop;
+    log::info!("serving CDH resources on FIFO {}", path.display());
+    let mut hangups = HANGUPS.load(Ordering::SeqCst);
+
+    while !stop.load(Ordering::SeqCst) {
+        let created = create_fifo(path, options.mode)?;
+
+        let file = fs::OpenOptions::new()
             .write(true)
-            .open(path)
-            .with_context(|| format!("failed to open FIFO {} for writing", path.display()))?;
+            .custom_flags(OFlag::O_NOFOLLOW.bits())
+            .open(path);
+        let mut file = match file {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                log::warn!("FIFO {} was deleted externally, recreating", path.display());
+                continue;
+            }
+            Err(e) if e.raw_os_error() == Some(Errno::ELOOP as i32) => {
+                log::error!("FIFO {} was replaced by a symlink; refusing", path.display());
+                bail!("FIFO path {} was replaced by a symlink", path.display());
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to open FIFO {} for writing", path.display()));
+            }
+        };
+        if stop.load(Ordering::SeqCst) {
+            // Woken by `serve` shutting down, not by a real reader.
+            break;
+        }
+
+        // Check what we actually opened before writing the seeds: if the node
+        // was swapped for a regular file or another FIFO, start over.
+        let opened = file
+            .metadata()
+            .with_context(|| format!("failed to stat opened FIFO {}", path.display()))?;
+        let same_node = NodeId {
+            dev: opened.dev(),
+            ino: opened.ino(),
+        } == created;
+        if !opened.file_type().is_fifo() || !same_node {
+            log::warn!(
+                "{} no longer refers to the FIFO we created, recreating",
+                path.display()
+            );
+            drop(file);
+            continue;
+        }
+        if !options.claim() {
+            // Another FIFO served the last of `KBS_SERVE_COUNT`; the reader gets EOF.
+            break;
+        }
 
-        file.write_all(json.as_bytes())
-            .context("failed to write CDH resources to FIFO")?;
+        let written = payload(path, &mut file);
+        if written.is_ok() {
+            wait_drained(&file, path);
+        }
         drop(file);
+        if let Err(e) = written {
+            fs::remove_file(path).ok();
+            return Err(e).with_context(|| {
+                format!("failed to write CDH resources to FIFO {}", path.display())
+            });
+        }
 
-        log::info!("served CDH resources to reader");
+        log::info!(target: AUDIT_TARGET, "served CDH resources to reader on {}", path.display());
         fs::remove_file(path).ok();
+
+        if options.exhausted() {
+            log::info!(
+                target: AUDIT_TARGET,
+                "served {SERVE_COUNT_ENV}={} reads, exiting",
+                options.served.load(Ordering::SeqCst)
+            );
+            break;
+        }
+        if options.rearm_on_sighup {
+            log::info!(
+                target: AUDIT_TARGET,
+                "FIFO {} removed until the next SIGHUP",
+                path.display()
+            );
+            while HANGUPS.load(Ordering::SeqCst) == hangups && !stop.load(Ordering::SeqCst) {
+                thread::sleep(WAKE_INTERVAL);
+            }
+            hangups = HANGUPS.load(Ordering::SeqCst);
+        }
     }
+
+    log::info!("stopped serving FIFO {}", path.display());
+    fs::remove_file(path).ok();
+    Ok(())
 }
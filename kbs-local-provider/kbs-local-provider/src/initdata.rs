@@ -1,48 +1,566 @@
 use anyhow::{Context, Result, bail};
+use nix::errno::Errno;
+use nix::fcntl::OFlag;
+use provider::crypto::{Argon2Params, Kdf};
 use serde::Deserialize;
-use sha2::{Digest, Sha256};
-use std::path::Path;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{ErrorKind, Read};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::resources::{self, Encoding};
 
 const DEFAULT_INIT_DATA_PATH: &str = "/run/confidential-containers/initdata/init_data.toml";
 const INIT_DATA_PATH_ENV: &str = "CC_INIT_DATA";
+const STRICT_ENV: &str = "KBS_INIT_DATA_STRICT";
+const WAIT_SECS_ENV: &str = "CC_INIT_DATA_WAIT_SECS";
+const DOMAIN_SEPARATOR_ENV: &str = "KBS_DOMAIN_SEPARATOR";
+const ALLOW_ENV_OVERRIDE_ENV: &str = "KBS_ALLOW_ENV_OVERRIDE";
+/// How often a missing init_data path is re-checked while waiting for it.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const DEFAULT_KEY_LENGTH: usize = 32;
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const GZIP_MAGIC: &[u8] = b"\x1F\x8B";
+/// Upper bound on decompressed init_data, to refuse gzip bombs.
+const MAX_DECOMPRESSED_LEN: u64 = 1024 * 1024;
+/// domain_separators left over from templates and examples rather than
+/// chosen for a deployment, compared case-insensitively.
+const PLACEHOLDER_DOMAIN_SEPARATORS: [&str; 7] =
+    ["changeme", "change-me", "example", "placeholder", "test", "todo", "xxx"];
 
 #[derive(Deserialize)]
 struct InitData {
+    algorithm: Option<String>,
     data: InitDataFields,
 }
 
+/// An overlay document only contributes its digest; its `[data]` is ignored.
+#[derive(Deserialize)]
+struct OverlayInitData {
+    algorithm: Option<String>,
+}
+
+/// Hash algorithm declared by the top-level init_data `algorithm` field.
+///
+/// The runtime measures init_data with this algorithm, so the digest used
+/// as HKDF salt must be computed the same way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    fn from_field(algorithm: Option<&str>) -> Result<Self> {
+        match algorithm {
+            None | Some("sha256") => Ok(Self::Sha256),
+            Some("sha384") => Ok(Self::Sha384),
+            Some("sha512") => Ok(Self::Sha512),
+            Some(other) => bail!(
+                "unsupported init_data algorithm {other:?} (expected sha256, sha384 or sha512)"
+            ),
+        }
+    }
+
+    fn digest(self, raw: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Sha256 => Sha256::digest(raw).to_vec(),
+            Self::Sha384 => Sha384::digest(raw).to_vec(),
+            Self::Sha512 => Sha512::digest(raw).to_vec(),
+        }
+    }
+}
+
+impl fmt::Display for DigestAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Sha256 => "sha256",
+            Self::Sha384 => "sha384",
+            Self::Sha512 => "sha512",
+        })
+    }
+}
+
+/// Keys read from the init_data `[data]` table.
+///
+/// CoCo components parse `[data]` as a string map, so every value here is a
+/// string (including `key_length`) to keep the document valid for them.
 #[derive(Deserialize)]
 struct InitDataFields {
+    argon2: Option<String>,
     domain_separator: Option<String>,
+    domain_separators: Option<String>,
+    encoding: Option<String>,
+    kdf: Option<String>,
+    key_length: Option<String>,
+    min_ikm_len: Option<String>,
+}
+
+/// A key to derive: its HKDF info, the resource path it is served under and
+/// how its value is encoded there.
+pub struct KeyDecl {
+    pub domain_separator: String,
+    pub resource: String,
+    pub encoding: Encoding,
 }
 
 pub struct ParsedInitData {
     pub domain_separator: String,
-    pub init_data_digest: [u8; 32],
+    /// Keys to derive and serve; the `domain_separator` key comes first.
+    pub keys: Vec<KeyDecl>,
+    pub algorithm: DigestAlgorithm,
+    pub init_data_digest: Vec<u8>,
+    pub kdf: Kdf,
+    pub key_length: usize,
+    /// Argon2id pre-stretch of the IKM declared in `data.argon2`, if any.
+    pub argon2: Option<Argon2Params>,
+    /// Shortest provider IKM accepted, from `data.min_ikm_len`; see
+    /// [`ParsedInitData::check_ikm_length`].
+    pub min_ikm_length: Option<usize>,
+    /// The designated document's path, or `None` when parsed from bytes.
+    pub source_path: Option<PathBuf>,
+    /// Whether `source_path` came from `CC_INIT_DATA` rather than the default.
+    pub from_env: bool,
 }
 
+impl ParsedInitData {
+    /// Enforce `data.min_ikm_len` on the IKM the provider returned (before
+    /// any Argon2 stretch, which would hide its real length), so the
+    /// measured document can refuse a downgrade to a weaker fallback
+    /// provider. A no-op without the field.
+    pub fn check_ikm_length(&self, ikm: &[u8], provider: impl fmt::Display) -> Result<()> {
+        if let Some(min) = self.min_ikm_length
+            && ikm.len() < min
+        {
+            bail!(
+                "the {provider} provider returned a {}-byte IKM, shorter than \
+                 data.min_ikm_len {min}",
+                ikm.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Parse init_data from `CC_INIT_DATA` (or the default path).
+///
+/// `CC_INIT_DATA` may list several comma-separated documents, e.g. a base
+/// and an overlay that are measured separately. The first one is the
+/// designated document: it supplies `algorithm` and every `[data]` field.
+/// The others only contribute their digest, so the HKDF salt binds to all
+/// of them: it becomes the digest of the concatenated per-document digests,
+/// in the order listed. With a single document the salt is its digest, as
+/// before.
+///
+/// For development, `KBS_DOMAIN_SEPARATOR` replaces `data.domain_separator`,
+/// but only alongside `KBS_ALLOW_ENV_OVERRIDE=1`; see [`apply_env_override`].
 pub fn parse() -> Result<ParsedInitData> {
-    let path = std::env::var(INIT_DATA_PATH_ENV)
-        .unwrap_or_else(|_| DEFAULT_INIT_DATA_PATH.to_string());
-    let path = Path::new(&path);
+    let env_paths = std::env::var(INIT_DATA_PATH_ENV).ok();
+    let from_env = env_paths.is_some();
+    let paths = env_paths.unwrap_or_else(|| DEFAULT_INIT_DATA_PATH.to_string());
+    let mut paths = paths.split(',').map(|path| Path::new(path.trim()));
+    let path = paths.next().expect("split yields at least one entry");
 
-    let raw = std::fs::read(path)
-        .with_context(|| format!("failed to read init_data from {}", path.display()))?;
+    let mut parsed = parse_bytes(&read(path)?)
+        .with_context(|| format!("invalid init_data at {}", path.display()))?;
+    parsed.source_path = Some(path.to_path_buf());
+    parsed.from_env = from_env;
 
-    let init_data: InitData = toml::from_str(
-        std::str::from_utf8(&raw).context("init_data.toml is not valid UTF-8")?,
-    )
-    .context("failed to parse init_data.toml")?;
+    let mut overlays = 0;
+    for overlay in paths {
+        let digest = overlay_digest(&read(overlay)?, parsed.algorithm)
+            .with_context(|| format!("invalid init_data overlay at {}", overlay.display()))?;
+        log::info!("binding init_data overlay {}", overlay.display());
+        parsed.init_data_digest.extend(digest);
+        overlays += 1;
+    }
+    if overlays > 0 {
+        parsed.init_data_digest = parsed.algorithm.digest(&parsed.init_data_digest);
+    }
+
+    apply_env_override(&mut parsed)?;
+    Ok(parsed)
+}
+
+/// Replace the primary domain_separator with `KBS_DOMAIN_SEPARATOR` when
+/// `KBS_ALLOW_ENV_OVERRIDE=1` is also set; without it the variable is
+/// ignored with a warning.
+///
+/// The init_data digest is still the salt, but the HKDF info no longer
+/// comes from the measured document, so anyone who can set the environment
+/// picks the derived key. Never enable this in production.
+fn apply_env_override(parsed: &mut ParsedInitData) -> Result<()> {
+    let Ok(domain_separator) = std::env::var(DOMAIN_SEPARATOR_ENV) else {
+        return Ok(());
+    };
+    if std::env::var(ALLOW_ENV_OVERRIDE_ENV).as_deref() != Ok("1") {
+        log::warn!("ignoring {DOMAIN_SEPARATOR_ENV}: it requires {ALLOW_ENV_OVERRIDE_ENV}=1");
+        return Ok(());
+    }
+    if domain_separator.is_empty() {
+        bail!("{DOMAIN_SEPARATOR_ENV} is empty");
+    }
+    if parsed.keys[1..]
+        .iter()
+        .any(|k| k.domain_separator == domain_separator)
+    {
+        bail!("{DOMAIN_SEPARATOR_ENV} {domain_separator:?} collides with data.domain_separators");
+    }
+
+    log::warn!(
+        "!!! {DOMAIN_SEPARATOR_ENV} overrides data.domain_separator \
+         ({:?} -> {domain_separator:?}): the derived keys are NO LONGER bound to \
+         the measured init_data; do not use in production",
+        parsed.domain_separator
+    );
+    parsed.keys[0].domain_separator = domain_separator.clone();
+    parsed.domain_separator = domain_separator;
+    Ok(())
+}
+
+/// Parse a single init_data document from its raw bytes, exactly as the
+/// runtime measured them (possibly gzipped): UTF-8 and TOML decoding, the
+/// domain_separator gate, the `[data]` options and the digest.
+///
+/// [`parse`] only adds path resolution, file reading and overlays on top,
+/// so other sources (stdin, NV, inline base64) can feed this directly.
+/// Malformed input of any kind yields an error, never a panic.
+pub fn parse_bytes(raw: &[u8]) -> Result<ParsedInitData> {
+    let init_data: InitData = toml::from_str(&decode(raw)?).context("failed to parse init_data")?;
+
+    let algorithm = DigestAlgorithm::from_field(init_data.algorithm.as_deref())?;
+
+    // Digest the bytes exactly as read from disk: this is what the runtime
+    // measured, so no canonicalization is applied even when a BOM was
+    // stripped, and a gzipped document is digested compressed.
+    let init_data_digest = algorithm.digest(raw);
 
     let domain_separator = match init_data.data.domain_separator {
         Some(ds) if !ds.is_empty() => ds,
         _ => bail!("data.domain_separator is missing or empty in init_data.toml (security gate)"),
     };
 
-    let init_data_digest: [u8; 32] = Sha256::digest(&raw).into();
+    let encoding = match init_data.data.encoding {
+        Some(encoding) => encoding.parse().context("invalid data.encoding in init_data.toml")?,
+        None => Encoding::default(),
+    };
+
+    let keys = key_declarations(
+        &domain_separator,
+        encoding,
+        init_data.data.domain_separators.as_deref(),
+    )?;
+    for key in &keys {
+        check_placeholder_domain_separator(&key.domain_separator)?;
+    }
+
+    let kdf = match init_data.data.kdf {
+        Some(kdf) => kdf.parse().context("invalid data.kdf in init_data.toml")?,
+        None => Kdf::default(),
+    };
+
+    let key_length = match init_data.data.key_length {
+        Some(len) => len
+            .parse()
+            .with_context(|| format!("data.key_length {len:?} is not a number"))?,
+        None => DEFAULT_KEY_LENGTH,
+    };
+    kdf.check_key_length(key_length)
+        .context("invalid data.key_length in init_data.toml")?;
+    if key_length != 32
+        && let Some(key) = keys.iter().find(|k| k.encoding == Encoding::Pem)
+    {
+        bail!(
+            "{} uses pem encoding, which requires a 32-byte Ed25519 seed \
+             (data.key_length = \"32\")",
+            key.resource
+        );
+    }
+
+    let argon2 = init_data
+        .data
+        .argon2
+        .map(|params| params.parse())
+        .transpose()
+        .context("invalid data.argon2 in init_data.toml")?;
+
+    let min_ikm_length = init_data
+        .data
+        .min_ikm_len
+        .map(|len| {
+            len.parse()
+                .with_context(|| format!("data.min_ikm_len {len:?} is not a number"))
+        })
+        .transpose()?;
 
     Ok(ParsedInitData {
         domain_separator,
+        keys,
+        algorithm,
         init_data_digest,
+        kdf,
+        key_length,
+        argon2,
+        min_ikm_length,
+        source_path: None,
+        from_env: false,
     })
 }
+
+/// Wait up to `CC_INIT_DATA_WAIT_SECS` (default 0) for `path` to appear.
+///
+/// Some runtimes mount init_data shortly after the provider starts; polling
+/// here removes that boot-order race. Once the time is up the read goes
+/// ahead anyway and reports the missing file as before.
+fn wait_for(path: &Path) -> Result<()> {
+    let secs = match std::env::var(WAIT_SECS_ENV) {
+        Ok(secs) => secs
+            .parse::<u64>()
+            .with_context(|| format!("{WAIT_SECS_ENV} {secs:?} is not a number of seconds"))?,
+        Err(_) => 0,
+    };
+    let deadline = Instant::now() + Duration::from_secs(secs);
+    let mut logged = false;
+    while fs_entry_missing(path) && Instant::now() < deadline {
+        if !logged {
+            log::info!("waiting up to {secs}s for init_data at {}", path.display());
+            logged = true;
+        }
+        std::thread::sleep(WAIT_POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+fn fs_entry_missing(path: &Path) -> bool {
+    matches!(std::fs::symlink_metadata(path), Err(e) if e.kind() == ErrorKind::NotFound)
+}
+
+/// Read one init_data document's raw on-disk bytes (what the runtime measured).
+///
+/// The path is opened with `O_NOFOLLOW`: a symlink planted in its place
+/// could redirect the read, and so the key binding, to another document.
+/// With `KBS_CHECK_OWNER=1` the opened file must also have the expected
+/// owner (see [`owner`](crate::owner)).
+fn read(path: &Path) -> Result<Vec<u8>> {
+    wait_for(path)?;
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(OFlag::O_NOFOLLOW.bits())
+        .open(path);
+    let mut file = match file {
+        Ok(file) => file,
+        Err(e) if e.raw_os_error() == Some(Errno::ELOOP as i32) => {
+            log::error!("init_data path {} is a symlink; refusing to follow it", path.display());
+            bail!("init_data path {} is a symlink", path.display());
+        }
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("failed to read init_data from {}", path.display()));
+        }
+    };
+    let meta = file
+        .metadata()
+        .with_context(|| format!("failed to stat init_data {}", path.display()))?;
+    crate::owner::check(&meta, "init_data", path)?;
+    let mut raw = Vec::new();
+    file.read_to_end(&mut raw)
+        .with_context(|| format!("failed to read init_data from {}", path.display()))?;
+    Ok(raw)
+}
+
+/// Digest an overlay document, which must declare the same algorithm as the
+/// designated one (or none, for sha256).
+fn overlay_digest(raw: &[u8], algorithm: DigestAlgorithm) -> Result<Vec<u8>> {
+    let overlay: OverlayInitData =
+        toml::from_str(&decode(raw)?).context("failed to parse init_data")?;
+    if DigestAlgorithm::from_field(overlay.algorithm.as_deref())? != algorithm {
+        bail!("overlay declares a different algorithm than the designated init_data ({algorithm})");
+    }
+    Ok(algorithm.digest(raw))
+}
+
+/// Turn a raw document into its TOML text: decompressed and without a BOM.
+fn decode(raw: &[u8]) -> Result<String> {
+    let document = decompress_if_gzip(raw)?;
+
+    let text = document.strip_prefix(UTF8_BOM).unwrap_or(&document);
+    if text.trim_ascii().is_empty() {
+        bail!("init_data is empty");
+    }
+
+    warn_on_byte_level_noise(&document);
+
+    let text = std::str::from_utf8(text).context("init_data is not valid UTF-8")?;
+    Ok(text.to_string())
+}
+
+/// Build the key list: the primary `domain_separator` key, served at the
+/// well-known resource in `data.encoding`, followed by one key per
+/// comma-separated entry in `data.domain_separators`, served at
+/// `default/key/<separator>`. An entry may pick its own encoding with a
+/// suffix (`app-b:hex`); otherwise it is base64.
+///
+/// Extra separators become part of a resource path, so they are limited to
+/// `[A-Za-z0-9._-]`, and must be distinct so no two keys share an HKDF info.
+fn key_declarations(
+    primary: &str,
+    primary_encoding: Encoding,
+    extra: Option<&str>,
+) -> Result<Vec<KeyDecl>> {
+    let mut keys = vec![KeyDecl {
+        domain_separator: primary.to_string(),
+        resource: resources::SEED_RESOURCE.to_string(),
+        encoding: primary_encoding,
+    }];
+
+    for entry in extra.into_iter().flat_map(|list| list.split(',')) {
+        let (separator, encoding) = match entry.trim().split_once(':') {
+            Some((separator, encoding)) => (
+                separator,
+                encoding.parse().with_context(|| {
+                    format!("invalid encoding in data.domain_separators entry {entry:?}")
+                })?,
+            ),
+            None => (entry.trim(), Encoding::default()),
+        };
+        if separator.is_empty() {
+            bail!("data.domain_separators contains an empty entry");
+        }
+        if !separator
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'))
+        {
+            bail!("data.domain_separators entry {separator:?} may only contain [A-Za-z0-9._-]");
+        }
+
+        let resource = resources::resource_for_separator(separator);
+        if keys
+            .iter()
+            .any(|k| k.domain_separator == separator || k.resource == resource)
+        {
+            bail!("data.domain_separators entry {separator:?} collides with another key");
+        }
+        keys.push(KeyDecl {
+            domain_separator: separator.to_string(),
+            resource,
+            encoding,
+        });
+    }
+
+    Ok(keys)
+}
+
+/// Warn, or with `KBS_INIT_DATA_STRICT=1` bail, if the domain_separator is
+/// a template value such as `changeme`: it points at a placeholder
+/// init_data shipped by mistake, whose keys anyone with the template shares.
+fn check_placeholder_domain_separator(domain_separator: &str) -> Result<()> {
+    if !PLACEHOLDER_DOMAIN_SEPARATORS
+        .iter()
+        .any(|placeholder| domain_separator.eq_ignore_ascii_case(placeholder))
+    {
+        return Ok(());
+    }
+    placeholder_found(&format!(
+        "domain_separator {domain_separator:?} looks like a placeholder left over from a template"
+    ))
+}
+
+/// Warn, or with `KBS_INIT_DATA_STRICT=1` bail, if a measured init_data
+/// digest is all-zero (or all-ones): that is a register nothing was ever
+/// extended into, e.g. an unextended PCR, so the HKDF salt is predictable.
+pub fn check_placeholder_digest(digest: &[u8]) -> Result<()> {
+    if !digest.iter().all(|&b| b == 0) && !digest.iter().all(|&b| b == 0xff) {
+        return Ok(());
+    }
+    placeholder_found(
+        "init_data digest is all-zero or all-ones: nothing was measured into it, so the HKDF \
+         salt is predictable",
+    )
+}
+
+fn placeholder_found(message: &str) -> Result<()> {
+    match std::env::var(STRICT_ENV).as_deref() {
+        Err(_) | Ok("0") => {
+            log::warn!("{message}");
+            Ok(())
+        }
+        Ok("1") => bail!("{message} ({STRICT_ENV}=1)"),
+        Ok(other) => bail!("unsupported {STRICT_ENV} {other:?} (expected 0 or 1)"),
+    }
+}
+
+/// Transparently gunzip init_data delivered compressed by the runtime.
+fn decompress_if_gzip(raw: &[u8]) -> Result<Cow<'_, [u8]>> {
+    if !raw.starts_with(GZIP_MAGIC) {
+        return Ok(Cow::Borrowed(raw));
+    }
+
+    let mut document = Vec::new();
+    flate2::read::GzDecoder::new(raw)
+        .take(MAX_DECOMPRESSED_LEN + 1)
+        .read_to_end(&mut document)
+        .context("failed to decompress gzipped init_data")?;
+    if document.len() as u64 > MAX_DECOMPRESSED_LEN {
+        bail!("decompressed init_data exceeds {MAX_DECOMPRESSED_LEN} bytes");
+    }
+
+    log::info!("decompressed gzipped init_data ({} -> {} bytes)", raw.len(), document.len());
+    Ok(Cow::Owned(document))
+}
+
+/// Warn about byte-level artifacts that change the digest (and therefore
+/// the derived key) without changing the logical TOML content.
+fn warn_on_byte_level_noise(raw: &[u8]) {
+    if raw.starts_with(UTF8_BOM) {
+        log::warn!(
+            "init_data.toml starts with a UTF-8 BOM; the digest covers it, so the derived key \
+             differs from one derived from the same document without a BOM"
+        );
+    }
+    if raw.contains(&b'\r') {
+        log::warn!(
+            "init_data.toml contains CR line endings; the digest covers them, so the derived key \
+             differs from one derived from the same document with LF line endings"
+        );
+    }
+    // A single trailing newline is the norm; anything beyond it is likely editor noise.
+    if raw.len() - raw.trim_ascii_end().len() > 1 {
+        log::warn!(
+            "init_data.toml ends with extra trailing whitespace; the digest covers it, so editing \
+             it changes the derived key"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_init_data_is_rejected() {
+        for raw in [&b""[..], b" \n\t"] {
+            let err = parse_bytes(raw).err().expect("empty init_data must not parse");
+            assert!(format!("{err:#}").contains("init_data is empty"), "{err:#}");
+        }
+    }
+
+    #[test]
+    fn gzipped_init_data_is_digested_compressed() {
+        use std::io::Write;
+
+        let document = b"algorithm = \"sha256\"\n[data]\ndomain_separator = \"gzip-app\"\n";
+        let mut encoder =
+            flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(document).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let parsed = parse_bytes(&compressed).expect("gzipped init_data parses");
+        assert_eq!(parsed.domain_separator, "gzip-app");
+        assert_eq!(parsed.init_data_digest, Sha256::digest(&compressed).to_vec());
+        assert_ne!(parsed.init_data_digest, Sha256::digest(document).to_vec());
+    }
+}
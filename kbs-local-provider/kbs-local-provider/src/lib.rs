@@ -0,0 +1,6 @@
+//! The input-handling half of kbs-local-provider (init_data parsing and the
+//! resources payload), split out of the binary so it can be fuzzed.
+
+pub mod initdata;
+pub mod owner;
+pub mod resources;
@@ -1,21 +1,277 @@
+mod atomic_file;
+mod backup;
+mod check;
+mod cli;
+mod credential;
 mod fifo;
-mod initdata;
+mod manifest;
+mod measured;
+mod namespace;
+mod params;
+mod pubkey;
+mod salt;
+mod scheme;
+mod selftest;
+mod socket;
+mod stretch;
+#[cfg(feature = "otel")]
+mod telemetry;
+mod watch;
 
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
+use kbs_local_provider::{initdata, resources};
+
+const SERVE_MODE_ENV: &str = "KBS_SERVE_MODE";
+const RESOURCE_FORMAT_ENV: &str = "CDH_RESOURCE_FORMAT";
+const LAZY_DERIVE_ENV: &str = "KBS_LAZY_DERIVE";
 
 fn main() -> Result<()> {
     env_logger::init();
+    #[cfg(feature = "otel")]
+    let _tracer_provider = telemetry::init()?;
 
-    let parsed = initdata::parse()?;
+    let args = cli::parse()?;
+    if args.scheme {
+        print!("{}", scheme::render());
+        return Ok(());
+    }
+    if args.check {
+        return check::run();
+    }
+    if let Some(iterations) = args.provider_selftest {
+        return selftest::run(iterations);
+    }
+    let mut parsed = initdata::parse()?;
+    measured::apply(&mut parsed)?;
+    if let Some(path) = &parsed.source_path {
+        let origin = if parsed.from_env { "CC_INIT_DATA" } else { "default path" };
+        log::info!("init_data: {} (from {origin})", path.display());
+    }
     log::info!("domain_separator: {}", parsed.domain_separator);
+    for decl in &parsed.keys[1..] {
+        log::info!("additional domain_separator: {} ({})", decl.domain_separator, decl.resource);
+    }
+    log::info!("init_data digest algorithm: {}", parsed.algorithm);
+    log::info!("kdf: {}, key_length: {}", parsed.kdf, parsed.key_length);
 
-    let provider = provider::detect_provider()?;
+    let cvm_type = provider::platform::detect_cvm_type();
+    log::info!("environment: {cvm_type}");
+    let provider = provider::detect_provider().inspect_err(|_| {
+        log::error!("seed provider detection report:\n{}", provider::diagnose());
+    })?;
+    let provider = bind_tpm_hmac(provider, &parsed.init_data_digest)?;
+    if args.require_tee {
+        if !provider.kind().is_hardware_rooted() {
+            bail!(
+                "seed provider {} is not hardware-rooted; refusing to run with --require-tee",
+                provider.kind()
+            );
+        }
+        if !cvm_type.has_hardware_root() {
+            bail!("environment {cvm_type} has no TEE or TPM device; refusing --require-tee");
+        }
+    }
+    if args.provision_if_needed {
+        provision_if_needed(provider.kind())?;
+    }
+    let salt = salt::resolve(&parsed.init_data_digest)?;
     let ikm = provider.ikm()?;
-    let seed = provider::crypto::derive_ed25519_seed(
-        &ikm, &parsed.init_data_digest, &parsed.domain_separator,
+    parsed.check_ikm_length(&ikm, provider.kind())?;
+    let ikm_fingerprint = watch::fingerprint(&ikm);
+    let ikm = stretch::apply(ikm, &parsed)?;
+    log::info!(
+        target: provider::AUDIT_TARGET,
+        "read {}-byte IKM from the {} provider",
+        ikm.len(),
+        provider.kind()
     );
+    let namespace = namespace::from_env()?;
+    let node_uuid = provider::crypto::derive_node_uuid(
+        &ikm,
+        salt.as_deref().map(Vec::as_slice),
+        &namespace.info(&parsed, 0),
+    )?;
+    log::info!("node uuid: {node_uuid}");
+    let keys = derive_keys(&parsed, &ikm, salt.as_deref().map(Vec::as_slice), &namespace)?;
+
+    // Only a 32-byte seed is an Ed25519 key; other lengths are opaque key material.
+    let public_keys: Vec<Option<[u8; 32]>> = keys
+        .iter()
+        .map(|key| {
+            <&[u8; 32]>::try_from(key.seed.as_slice())
+                .ok()
+                .map(provider::crypto::ed25519_public_key)
+        })
+        .collect();
+    for (key, public_key) in keys.iter().zip(&public_keys) {
+        if let Some(public_key) = public_key {
+            log::info!("{} ed25519 public key: {}", key.resource, pubkey::hex(public_key));
+        }
+    }
+
+    let entries: Vec<_> = keys
+        .iter()
+        .zip(&public_keys)
+        .map(|(key, &public_key)| manifest::ManifestEntry {
+            resource: &key.resource,
+            public_key,
+        })
+        .collect();
+
+    if let Some(path) = &args.export_params {
+        // The TPM IKM is the AK public key (plus public counters), unless a
+        // TPM HMAC is mixed in; every other provider's IKM is secret.
+        let ikm_public = provider.kind() == provider::ProviderKind::Tpm
+            && provider::tpm::hmac_key_handle_from_env()?.is_none();
+        let raw_ikm = if ikm_public { Some(provider.ikm()?) } else { None };
+        return params::export(
+            path,
+            &params::DerivationParams {
+                provider: provider.kind(),
+                ikm: raw_ikm.as_deref().map(Vec::as_slice),
+                argon2: stretch::params(&parsed)?,
+                parsed: &parsed,
+                salt: salt.as_deref().map(Vec::as_slice),
+                salt_withheld: salt::is_peppered(),
+                namespace: &namespace,
+                entries: &entries,
+            },
+        );
+    }
+
+    if let Some(path) = &args.backup {
+        return backup::write(path, &keys);
+    }
+
+    if args.manifest {
+        print!("{}", manifest::render(&entries, args.manifest_format));
+        return Ok(());
+    }
+
+    // The primary key is the first declared one.
+    pubkey::write_if_requested(public_keys[0].as_ref())?;
+    pubkey::write_public_resources_if_requested(&manifest::public_resources_json(
+        provider.kind(),
+        &entries,
+    ))?;
 
-    fifo::serve(&seed)?;
+    let format = match std::env::var(RESOURCE_FORMAT_ENV) {
+        Ok(format) => format
+            .parse()
+            .with_context(|| format!("invalid {RESOURCE_FORMAT_ENV}"))?,
+        Err(_) => resources::Format::default(),
+    };
+    // With KBS_LAZY_DERIVE=1 nothing secret outlives the startup derivation
+    // above: the IKM and seeds are re-derived for every reader and zeroized
+    // once written, at the cost of a provider read (and any Argon2 pass)
+    // per connection.
+    let lazy = std::env::var(LAZY_DERIVE_ENV).is_ok_and(|v| v == "1");
+    let mut keys = Some(keys);
+    if lazy {
+        log::info!(
+            target: provider::AUDIT_TARGET,
+            "deriving keys per reader ({LAZY_DERIVE_ENV}=1); startup keys dropped"
+        );
+        drop(ikm);
+        keys = None;
+    }
+    let payload = |sink: &mut dyn std::io::Write| -> Result<()> {
+        match &keys {
+            Some(keys) => resources::write_streaming(keys, format, sink)?,
+            None => {
+                let ikm = provider.ikm()?;
+                parsed.check_ikm_length(&ikm, provider.kind())?;
+                let ikm = stretch::apply(ikm, &parsed)?;
+                let salt = salt.as_deref().map(Vec::as_slice);
+                let keys = derive_keys(&parsed, &ikm, salt, &namespace)?;
+                resources::write_streaming(&keys, format, sink)?;
+            }
+        }
+        Ok(())
+    };
+    watch::while_serving(provider.as_ref(), &ikm_fingerprint, || {
+        match std::env::var(SERVE_MODE_ENV).as_deref() {
+            Err(_) | Ok("fifo") => {
+                fifo::serve(&fifo::paths_from_env()?, |_, sink| payload(sink))?
+            }
+            Ok("socket") => socket::serve(payload)?,
+            Ok("systemd-cred") => match &keys {
+                Some(keys) => credential::write(keys, format)?,
+                None => {
+                    bail!("{LAZY_DERIVE_ENV}=1 does not apply to systemd-cred, which writes once")
+                }
+            },
+            Ok(other) => bail!(
+                "unsupported {SERVE_MODE_ENV} {other:?} (expected fifo, socket or systemd-cred)"
+            ),
+        }
+        Ok(())
+    })
+}
+
+/// Create the TPM AK if it is missing, with the same `AAI_*` options and
+/// logic as attestation-agent-init, so the first IKM read finds it.
+fn provision_if_needed(kind: provider::ProviderKind) -> Result<()> {
+    use provider::tpm::provision::{ProvisionOptions, provision_ak};
 
+    if kind != provider::ProviderKind::Tpm {
+        log::info!("--provision-if-needed: the {kind} provider needs no provisioning");
+        return Ok(());
+    }
+    let options = ProvisionOptions::from_env()?;
+    if provision_ak(provider::tpm::tcti_from_env()?, &options)? {
+        log::info!("provisioned an AK at handle {:#X}", options.ak_handle);
+    }
     Ok(())
 }
+
+/// With `TPM_HMAC_KEY_HANDLE` set, swap the detected TPM provider for one
+/// that also mixes a TPM HMAC of the init_data digest into the IKM.
+fn bind_tpm_hmac(
+    provider: Box<dyn provider::SeedProvider>,
+    init_data_digest: &[u8],
+) -> Result<Box<dyn provider::SeedProvider>> {
+    let Some(handle) = provider::tpm::hmac_key_handle_from_env()? else {
+        return Ok(provider);
+    };
+    if provider.kind() != provider::ProviderKind::Tpm {
+        bail!("TPM_HMAC_KEY_HANDLE is set but the {} provider was detected", provider.kind());
+    }
+    log::info!("binding the IKM to a TPM HMAC of the init_data digest (key {handle:#X})");
+    Ok(Box::new(
+        provider::tpm::TpmSeedProvider::default().with_hmac_key(handle, init_data_digest),
+    ))
+}
+
+/// Derive the seed for every declared key from the (stretched) IKM.
+fn derive_keys(
+    parsed: &initdata::ParsedInitData,
+    ikm: &[u8],
+    salt: Option<&[u8]>,
+    namespace: &namespace::Namespace,
+) -> Result<Vec<resources::ServedKey>> {
+    let mut keys = Vec::with_capacity(parsed.keys.len());
+    for (index, decl) in parsed.keys.iter().enumerate() {
+        let seed = provider::crypto::derive_key(
+            parsed.kdf,
+            ikm,
+            salt,
+            &namespace.info(parsed, index),
+            parsed.key_length,
+        )?;
+        keys.push(resources::ServedKey {
+            resource: decl.resource.clone(),
+            seed,
+            encoding: decl.encoding,
+        });
+    }
+    log::info!(
+        target: provider::AUDIT_TARGET,
+        "derived {} keys ({}, {} bytes): {}",
+        keys.len(),
+        parsed.kdf,
+        parsed.key_length,
+        keys.iter().map(|k| k.resource.as_str()).collect::<Vec<_>>().join(", ")
+    );
+    Ok(keys)
+}
@@ -0,0 +1,80 @@
+use anyhow::{Result, bail};
+use provider::ProviderKind;
+use std::str::FromStr;
+
+use crate::pubkey::hex;
+
+/// Output format of `--manifest`, chosen with `--manifest-format`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ManifestFormat {
+    /// A single `{"keys": [...]}` document.
+    #[default]
+    Json,
+    /// One key object per line.
+    Jsonl,
+}
+
+impl FromStr for ManifestFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(Self::Json),
+            "jsonl" => Ok(Self::Jsonl),
+            _ => bail!("unsupported manifest format {s:?} (expected json or jsonl)"),
+        }
+    }
+}
+
+/// Public description of one served key. Never holds secret material.
+pub struct ManifestEntry<'a> {
+    pub resource: &'a str,
+    /// Ed25519 public key, or `None` for opaque (non-32-byte) key material.
+    pub public_key: Option<[u8; 32]>,
+}
+
+/// Render the manifest: each key's resource path, type and public key.
+pub fn json(entries: &[ManifestEntry]) -> String {
+    format!("{{\"keys\": {}}}\n", keys_json(entries))
+}
+
+/// Render the manifest as JSON Lines: one key object per line, for
+/// stream-processing tools.
+pub fn jsonl(entries: &[ManifestEntry]) -> String {
+    entries.iter().map(|entry| key_json(entry) + "\n").collect()
+}
+
+/// Render the manifest in `format`.
+pub fn render(entries: &[ManifestEntry], format: ManifestFormat) -> String {
+    match format {
+        ManifestFormat::Json => json(entries),
+        ManifestFormat::Jsonl => jsonl(entries),
+    }
+}
+
+/// Render the public resources document: the manifest plus provider metadata.
+pub fn public_resources_json(provider: ProviderKind, entries: &[ManifestEntry]) -> String {
+    format!(
+        "{{\"provider\": \"{provider}\", \"keys\": {}}}\n",
+        keys_json(entries)
+    )
+}
+
+fn keys_json(entries: &[ManifestEntry]) -> String {
+    let keys: Vec<String> = entries.iter().map(key_json).collect();
+    format!("[{}]", keys.join(", "))
+}
+
+fn key_json(entry: &ManifestEntry) -> String {
+    match &entry.public_key {
+        Some(pk) => format!(
+            "{{\"resource\": \"{}\", \"type\": \"ed25519\", \"public_key\": \"{}\"}}",
+            entry.resource,
+            hex(pk)
+        ),
+        None => format!(
+            "{{\"resource\": \"{}\", \"type\": \"raw\", \"public_key\": null}}",
+            entry.resource
+        ),
+    }
+}
@@ -0,0 +1,127 @@
+use anyhow::{Context, Result, bail};
+use std::path::Path;
+
+use kbs_local_provider::initdata::{self, ParsedInitData};
+
+const DIGEST_SOURCE_ENV: &str = "KBS_INIT_DATA_DIGEST_SOURCE";
+const DEFAULT_IMA_LOG: &str = "/sys/kernel/security/ima/ascii_runtime_measurements";
+
+/// Take the init_data digest (the HKDF salt) from where it was measured at
+/// boot rather than only from hashing the file now, per
+/// `KBS_INIT_DATA_DIGEST_SOURCE`:
+///
+/// - `file` (the default): the digest of the file as read.
+/// - `ima[:<log>]`: the last IMA entry for the init_data path in the ASCII
+///   measurement log (default `/sys/kernel/security/ima/ascii_runtime_measurements`),
+///   which must use the init_data `algorithm`.
+/// - `tsm:<path>`: a measurement register exposed as a file, e.g. a
+///   configfs-tsm field holding the TDX MRCONFIGID or SNP HOSTDATA, as raw
+///   bytes or hex; zero padding past the digest length is allowed.
+/// - `pcr:<index>`: the value of a TPM PCR in the bank of the init_data
+///   `algorithm`.
+///
+/// An IMA or TSM value must equal the digest of the file: a mismatch means
+/// the file changed after it was measured (or overlays are in use, which
+/// these sources cannot measure), and is an error. A PCR holds an extend
+/// chain, not the file digest, so its value replaces the digest outright;
+/// the keys are then bound to that PCR rather than to the document.
+pub fn apply(parsed: &mut ParsedInitData) -> Result<()> {
+    let source = match std::env::var(DIGEST_SOURCE_ENV) {
+        Err(_) => return Ok(()),
+        Ok(source) if source == "file" => return Ok(()),
+        Ok(source) => source,
+    };
+    let (kind, arg) = source.split_once(':').unwrap_or((&source, ""));
+    let measured = match kind {
+        "ima" => {
+            let log = if arg.is_empty() { DEFAULT_IMA_LOG } else { arg };
+            ima_digest(Path::new(log), parsed)?
+        }
+        "tsm" if !arg.is_empty() => tsm_digest(Path::new(arg), parsed.init_data_digest.len())?,
+        "pcr" => {
+            let index = arg
+                .parse()
+                .with_context(|| format!("{DIGEST_SOURCE_ENV} PCR index {arg:?} is not a number"))?;
+            let value = provider::tpm::read_pcr(index, &parsed.algorithm.to_string())?;
+            initdata::check_placeholder_digest(&value)
+                .with_context(|| format!("PCR {index} as the init_data digest"))?;
+            log::warn!(
+                "using PCR {index} ({}) as the init_data digest; the keys are bound to the PCR, \
+                 not to the init_data document",
+                parsed.algorithm
+            );
+            parsed.init_data_digest = value;
+            return Ok(());
+        }
+        _ => bail!(
+            "unsupported {DIGEST_SOURCE_ENV} {source:?} (expected file, ima[:<log>], \
+             tsm:<path> or pcr:<index>)"
+        ),
+    };
+
+    if !provider::crypto::ct_eq(&measured, &parsed.init_data_digest) {
+        bail!(
+            "init_data digest from {source} does not match the file: it changed after it \
+             was measured (or uses overlays, which {source} does not cover)"
+        );
+    }
+    log::info!("init_data digest matches the measurement from {source}");
+    parsed.init_data_digest = measured;
+    Ok(())
+}
+
+/// The file digest of the last IMA entry for the init_data path.
+///
+/// Lines are `<pcr> <template hash> <template> <file hash> <path> ...`,
+/// with the file hash as `<algorithm>:<hex>` in the `ima-ng` and `ima-sig`
+/// templates.
+fn ima_digest(log: &Path, parsed: &ParsedInitData) -> Result<Vec<u8>> {
+    let path = parsed
+        .source_path
+        .as_deref()
+        .context("the IMA digest source needs init_data read from a file")?;
+    let contents = std::fs::read_to_string(log)
+        .with_context(|| format!("failed to read IMA log {}", log.display()))?;
+    let entry = contents
+        .lines()
+        .rev()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().skip(3);
+            Some((fields.next()?, fields.next()?))
+        })
+        .find(|&(_, name)| Path::new(name) == path)
+        .with_context(|| format!("no IMA entry for {} in {}", path.display(), log.display()))?
+        .0;
+    let Some((algorithm, digest)) = entry.split_once(':') else {
+        bail!("IMA entry for {} has no algorithm (template ima?)", path.display());
+    };
+    if algorithm != parsed.algorithm.to_string() {
+        bail!(
+            "IMA measured {} with {algorithm}, but init_data declares {}",
+            path.display(),
+            parsed.algorithm
+        );
+    }
+    crate::salt::decode_hex(digest)
+        .with_context(|| format!("invalid IMA file hash for {}", path.display()))
+}
+
+/// A digest-sized measurement register read from `path`.
+fn tsm_digest(path: &Path, digest_len: usize) -> Result<Vec<u8>> {
+    let raw = std::fs::read(path)
+        .with_context(|| format!("failed to read measurement {}", path.display()))?;
+    let text = String::from_utf8_lossy(&raw);
+    let mut value = match crate::salt::decode_hex(text.trim()) {
+        Ok(value) => value,
+        Err(_) => raw,
+    };
+    if value.len() < digest_len || value[digest_len..].iter().any(|&b| b != 0) {
+        bail!(
+            "measurement {} is {} bytes, not a {digest_len}-byte digest (zero padded)",
+            path.display(),
+            value.len()
+        );
+    }
+    value.truncate(digest_len);
+    Ok(value)
+}
@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use provider::InfoTemplate;
+
+use kbs_local_provider::initdata::ParsedInitData;
+
+const INFO_PREFIX_ENV: &str = "KBS_INFO_PREFIX";
+const DEPLOYMENT_LABEL_ENV: &str = "KBS_DEPLOYMENT_LABEL";
+const INFO_TEMPLATE_ENV: &str = "KBS_INFO_TEMPLATE";
+
+/// How the HKDF info of every key is built: a global prefix and a template.
+pub struct Namespace {
+    prefix: String,
+    template: InfoTemplate,
+}
+
+/// The namespace from `KBS_INFO_PREFIX`, `KBS_DEPLOYMENT_LABEL` and
+/// `KBS_INFO_TEMPLATE`.
+///
+/// The prefix is prepended to every domain_separator before derivation,
+/// namespacing all of a system's keys apart from other systems sharing this
+/// scheme. Changing it rotates every derived key (and the node UUID).
+///
+/// The deployment label adds `deployment:<label>:` after the prefix, so
+/// re-imaging the same TPM under a new label rotates the identity although
+/// init_data and the AK are unchanged. Anyone re-deriving or verifying the
+/// keys must use the same label.
+///
+/// `KBS_INFO_TEMPLATE` replaces the default `{prefix}{domain_separator}`
+/// info for interop with a KBS that builds its info differently (see
+/// [`InfoTemplate`]); here `{key_type}` is `ed25519` for 32-byte keys and
+/// `raw` otherwise, and `{index}` is the key's position in init_data, the
+/// `domain_separator` key being 0. A different template means different
+/// keys, so consumers must agree on it too.
+pub fn from_env() -> Result<Namespace> {
+    let mut prefix = std::env::var(INFO_PREFIX_ENV).unwrap_or_default();
+    if !prefix.is_empty() {
+        log::info!("namespacing HKDF info with {INFO_PREFIX_ENV} {prefix:?}");
+    }
+    let label = std::env::var(DEPLOYMENT_LABEL_ENV).unwrap_or_default();
+    if !label.is_empty() {
+        log::info!("namespacing HKDF info with {DEPLOYMENT_LABEL_ENV} {label:?}");
+        prefix.push_str(&format!("deployment:{label}:"));
+    }
+    let template = match std::env::var(INFO_TEMPLATE_ENV) {
+        Ok(template) => {
+            let template = template
+                .parse()
+                .with_context(|| format!("invalid {INFO_TEMPLATE_ENV}"))?;
+            log::info!("building HKDF info from {INFO_TEMPLATE_ENV} {template}");
+            template
+        }
+        Err(_) => InfoTemplate::default(),
+    };
+    Ok(Namespace { prefix, template })
+}
+
+impl Namespace {
+    /// The HKDF info for the `index`th key declared in `parsed`.
+    pub fn info(&self, parsed: &ParsedInitData, index: usize) -> String {
+        let key_type = if parsed.key_length == 32 { "ed25519" } else { "raw" };
+        self.template.render(
+            &self.prefix,
+            &parsed.keys[index].domain_separator,
+            key_type,
+            Some(index as u32),
+        )
+    }
+}
@@ -0,0 +1,51 @@
+//! Optional ownership checks on the init_data file and the FIFO directory.
+//!
+//! On a multi-tenant host a co-tenant who can pre-create the init_data file
+//! or the resources directory could plant their own document or hijack the
+//! served FIFO. With `KBS_CHECK_OWNER=1` both must be owned by root, or by
+//! the uid in `KBS_OWNER_UID` for non-root deployments.
+
+use anyhow::{Context, Result, bail};
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+const CHECK_OWNER_ENV: &str = "KBS_CHECK_OWNER";
+const OWNER_UID_ENV: &str = "KBS_OWNER_UID";
+
+/// The uid files must be owned by, or `None` if the check is off.
+pub fn expected_uid() -> Result<Option<u32>> {
+    match std::env::var(CHECK_OWNER_ENV).as_deref() {
+        Err(_) | Ok("0") => return Ok(None),
+        Ok("1") => {}
+        Ok(other) => bail!("unsupported {CHECK_OWNER_ENV} {other:?} (expected 0 or 1)"),
+    }
+    match std::env::var(OWNER_UID_ENV) {
+        Ok(uid) => uid
+            .parse()
+            .map(Some)
+            .with_context(|| format!("{OWNER_UID_ENV} {uid:?} is not a uid")),
+        Err(_) => Ok(Some(0)),
+    }
+}
+
+/// Bail unless `meta` (of the `what` at `path`) is owned by the expected uid.
+/// A no-op unless `KBS_CHECK_OWNER=1`.
+pub fn check(meta: &Metadata, what: &str, path: &Path) -> Result<()> {
+    let Some(uid) = expected_uid()? else {
+        return Ok(());
+    };
+    if meta.uid() != uid {
+        log::error!(
+            "{what} {} is owned by uid {}, expected {uid}; refusing it",
+            path.display(),
+            meta.uid()
+        );
+        bail!(
+            "{what} {} is owned by uid {}, not the expected uid {uid} ({CHECK_OWNER_ENV}=1)",
+            path.display(),
+            meta.uid()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use anyhow::{Context, Result};
+use provider::ProviderKind;
+use provider::crypto::Argon2Params;
+use std::path::Path;
+
+use kbs_local_provider::initdata::ParsedInitData;
+
+use crate::atomic_file;
+use crate::manifest::ManifestEntry;
+use crate::pubkey::hex;
+
+/// Everything besides the IKM that a derivation depends on, for
+/// `--export-params`.
+pub struct DerivationParams<'a> {
+    pub provider: ProviderKind,
+    /// The raw provider IKM, or `None` if it is secret and so withheld.
+    pub ikm: Option<&'a [u8]>,
+    pub argon2: Option<Argon2Params>,
+    pub parsed: &'a ParsedInitData,
+    /// The HKDF salt (`None` is HKDF's empty salt).
+    pub salt: Option<&'a [u8]>,
+    /// The salt is peppered, hence secret, and must not be exported.
+    pub salt_withheld: bool,
+    pub namespace: &'a crate::namespace::Namespace,
+    pub entries: &'a [ManifestEntry<'a>],
+}
+
+/// Write `params` as JSON to `path` (0644, atomically).
+///
+/// The document only ever holds public values. Inputs that are secret for
+/// this deployment (a keyring or SMBIOS IKM, a TPM HMAC, a peppered salt)
+/// are left out and listed under `"withheld"`; `"sensitive"` lists the
+/// exported fields that are secret, which is always none.
+pub fn export(path: &Path, params: &DerivationParams) -> Result<()> {
+    atomic_file::write(path, render(params)?.as_bytes(), 0o644)?;
+    log::info!("wrote derivation parameters to {}", path.display());
+    Ok(())
+}
+
+fn render(params: &DerivationParams) -> Result<String> {
+    let mut withheld = Vec::new();
+    let ikm = match params.ikm {
+        Some(ikm) => format!("\"{}\"", hex(ikm)),
+        None => {
+            withheld.push("\"ikm\"");
+            "null".to_string()
+        }
+    };
+    let salt = match params.salt {
+        _ if params.salt_withheld => {
+            withheld.push("\"salt\"");
+            "null".to_string()
+        }
+        Some(salt) => format!("\"{}\"", hex(salt)),
+        None => "null".to_string(),
+    };
+    let argon2 = match params.argon2 {
+        Some(argon2) => format!("\"{argon2}\""),
+        None => "null".to_string(),
+    };
+
+    let parsed = params.parsed;
+    let mut keys = Vec::with_capacity(parsed.keys.len());
+    for (index, (decl, entry)) in parsed.keys.iter().zip(params.entries).enumerate() {
+        let info = params.namespace.info(parsed, index);
+        let public_key = match &entry.public_key {
+            Some(pk) => format!("\"{}\"", hex(pk)),
+            None => "null".to_string(),
+        };
+        keys.push(format!(
+            "{{\"resource\": {}, \"info\": {}, \"encoding\": \"{}\", \"ed25519\": {public_key}}}",
+            string(&decl.resource)?,
+            string(&info)?,
+            decl.encoding
+        ));
+    }
+
+    Ok(format!(
+        "{{\"scheme_version\": {}, \"provider\": \"{}\", \"ikm\": {ikm}, \"argon2\": {argon2}, \
+         \"init_data_digest_algorithm\": \"{}\", \"init_data_digest\": \"{}\", \
+         \"salt\": {salt}, \"kdf\": \"{}\", \"key_length\": {}, \"keys\": [{}], \
+         \"sensitive\": [], \"withheld\": [{}]}}\n",
+        provider::crypto::DERIVATION_SCHEME_VERSION,
+        params.provider,
+        parsed.algorithm,
+        hex(&parsed.init_data_digest),
+        parsed.kdf,
+        parsed.key_length,
+        keys.join(", "),
+        withheld.join(", ")
+    ))
+}
+
+fn string(value: &str) -> Result<String> {
+    serde_json::to_string(value).context("failed to encode a JSON string")
+}
@@ -0,0 +1,49 @@
+use anyhow::{Result, bail};
+use std::path::Path;
+
+use crate::atomic_file;
+
+const PUBKEY_OUT_ENV: &str = "KBS_PUBKEY_OUT";
+const PUBLIC_RESOURCES_PATH_ENV: &str = "KBS_PUBLIC_RESOURCES_PATH";
+
+/// Write the derived public key to the path in `KBS_PUBKEY_OUT`, if set.
+///
+/// The file holds `{"ed25519": "<hex>"}` so a later boot step can register the
+/// TEE identity. It is written to a temporary file and renamed into place, so
+/// readers never see a partial document. Only public material is written.
+pub fn write_if_requested(public_key: Option<&[u8; 32]>) -> Result<()> {
+    let Ok(path) = std::env::var(PUBKEY_OUT_ENV) else {
+        return Ok(());
+    };
+    let path = Path::new(&path);
+
+    let Some(public_key) = public_key else {
+        bail!("{PUBKEY_OUT_ENV} requires a 32-byte Ed25519 seed (data.key_length = \"32\")");
+    };
+
+    let json = format!("{{\"ed25519\": \"{}\"}}\n", hex(public_key));
+    atomic_file::write(path, json.as_bytes(), 0o644)?;
+
+    log::info!("wrote derived public key to {}", path.display());
+    Ok(())
+}
+
+/// Write the public resources document to `KBS_PUBLIC_RESOURCES_PATH`, if set.
+///
+/// Unlike the secret FIFO this is a plain world-readable (0644) file, so
+/// unprivileged consumers can fetch the identity's public parts.
+pub fn write_public_resources_if_requested(json: &str) -> Result<()> {
+    let Ok(path) = std::env::var(PUBLIC_RESOURCES_PATH_ENV) else {
+        return Ok(());
+    };
+    let path = Path::new(&path);
+
+    atomic_file::write(path, json.as_bytes(), 0o644)?;
+
+    log::info!("wrote public resources to {}", path.display());
+    Ok(())
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
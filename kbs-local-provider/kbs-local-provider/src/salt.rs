@@ -0,0 +1,92 @@
+use anyhow::{Context, Result, bail};
+use provider::SecretBytes;
+
+const SALT_ENV: &str = "KBS_HKDF_SALT";
+const EXTRA_SALT_FILE_ENV: &str = "KBS_EXTRA_SALT_FILE";
+
+/// Resolve the HKDF salt: the init_data digest unless `KBS_HKDF_SALT` is set,
+/// peppered with `KBS_EXTRA_SALT_FILE` if that is set.
+///
+/// `KBS_HKDF_SALT` takes a hex string, or `none` for HKDF's default all-zero
+/// salt. Either way the derived keys are no longer bound to the measured
+/// init_data through the salt, only through whatever the explicit value
+/// itself is tied to, so this is meant for bridging to KBS setups with a
+/// different salt convention.
+///
+/// `KBS_EXTRA_SALT_FILE` names a file holding an operator-provisioned
+/// pepper, mixed in as HMAC-SHA256(pepper, salt). Knowing the IKM and
+/// init_data is then not enough to derive the keys, which only holds while
+/// the pepper stays secret: protect the file like a key, since anyone with
+/// it and the (public) AK and init_data can derive every key. Adding,
+/// changing or removing it rotates all keys.
+pub fn resolve(init_data_digest: &[u8]) -> Result<Option<SecretBytes>> {
+    let salt = base_salt(init_data_digest)?;
+    let Ok(path) = std::env::var(EXTRA_SALT_FILE_ENV) else {
+        return Ok(salt.map(SecretBytes::new));
+    };
+
+    let pepper = SecretBytes::new(
+        std::fs::read(&path)
+            .with_context(|| format!("failed to read {EXTRA_SALT_FILE_ENV} {path:?}"))?,
+    );
+    if pepper.is_empty() {
+        bail!("{EXTRA_SALT_FILE_ENV} {path:?} is empty");
+    }
+    log::info!("mixing the pepper from {path:?} into the HKDF salt");
+    Ok(Some(provider::crypto::pepper_salt(salt.as_deref(), &pepper)))
+}
+
+/// Whether [`resolve`] mixes in a pepper, making the salt secret.
+pub fn is_peppered() -> bool {
+    std::env::var_os(EXTRA_SALT_FILE_ENV).is_some()
+}
+
+fn base_salt(init_data_digest: &[u8]) -> Result<Option<Vec<u8>>> {
+    let Ok(value) = std::env::var(SALT_ENV) else {
+        return Ok(Some(init_data_digest.to_vec()));
+    };
+
+    log::warn!(
+        "using {SALT_ENV} instead of the init_data digest as HKDF salt; \
+         derived keys are not bound to init_data by the salt"
+    );
+    if value == "none" {
+        return Ok(None);
+    }
+    decode_hex(&value)
+        .map(Some)
+        .with_context(|| format!("{SALT_ENV} is not a hex string or \"none\""))
+}
+
+pub fn decode_hex(value: &str) -> Result<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        bail!("odd number of hex digits");
+    }
+    value
+        .as_bytes()
+        .chunks_exact(2)
+        .map(|pair| {
+            // from_str_radix alone would accept a sign, e.g. "+f".
+            if !pair.iter().all(u8::is_ascii_hexdigit) {
+                bail!("invalid hex byte {:?}", String::from_utf8_lossy(pair));
+            }
+            let digits = std::str::from_utf8(pair).expect("hex digits are ASCII");
+            Ok(u8::from_str_radix(digits, 16).expect("two hex digits fit a byte"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_hex_accepts_only_hex_digits() {
+        assert_eq!(decode_hex("0fA0").unwrap(), [0x0f, 0xa0]);
+        assert!(decode_hex("+f+f").is_err());
+        assert!(decode_hex("-1").is_err());
+        assert!(decode_hex(" f").is_err());
+        assert!(decode_hex("é").is_err());
+        assert!(decode_hex("abc").is_err());
+    }
+}
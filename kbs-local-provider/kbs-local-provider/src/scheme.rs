@@ -0,0 +1,19 @@
+use provider::crypto::DERIVATION_SCHEME_VERSION;
+
+/// The derivation scheme this build implements, for `--scheme`, as one JSON
+/// object on one line.
+///
+/// The fields describe the default scheme and are stable: consumers should
+/// compare `version` against the one they implement before re-deriving or
+/// verifying keys; a different version derives different keys.
+pub fn render() -> String {
+    format!(
+        "{{\"version\": {DERIVATION_SCHEME_VERSION}, \"kdf\": \"hkdf-sha256\", \
+         \"kdfs\": [\"hkdf-sha256\", \"hkdf-sha512\"], \
+         \"salt\": \"init_data digest\", \"init_data_digest_algorithm\": \"sha256\", \
+         \"info\": \"<KBS_INFO_PREFIX>[deployment:<KBS_DEPLOYMENT_LABEL>:]<domain_separator>\", \
+         \"ikm\": {{\"tpm\": \"AK SubjectPublicKeyInfo DER\", \"keyring\": \"key payload\", \
+         \"hardware\": \"SMBIOS fields and operator secret\"}}, \"key_length\": 32, \
+         \"ed25519\": \"seed = OKM\"}}\n"
+    )
+}
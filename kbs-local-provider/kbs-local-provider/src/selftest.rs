@@ -0,0 +1,32 @@
+use anyhow::{Context, Result, bail};
+
+/// Qualify the detected provider by reading its IKM `iterations` times and
+/// checking every read matches the first, in constant time.
+///
+/// Prints the provider, the IKM length and PASS/FAIL to stdout, never the
+/// IKM itself, and errors on any mismatch so the exit status is nonzero.
+pub fn run(iterations: u32) -> Result<()> {
+    let provider = provider::detect_provider()?;
+    println!("provider: {}", provider.kind());
+
+    let first = provider.ikm().context("IKM read 1 failed")?;
+    println!("ikm length: {} bytes", first.len());
+
+    let mut mismatches = 0;
+    for read in 2..=iterations {
+        let ikm = provider
+            .ikm()
+            .with_context(|| format!("IKM read {read} of {iterations} failed"))?;
+        if !provider::crypto::ct_eq(&first, &ikm) {
+            println!("read {read}: IKM differs from read 1");
+            mismatches += 1;
+        }
+    }
+
+    if mismatches > 0 {
+        println!("FAIL {mismatches} of {iterations} reads differed");
+        bail!("provider returned unstable IKM");
+    }
+    println!("PASS {iterations} identical reads");
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use anyhow::{Context, Result};
+use nix::sys::socket::getsockopt;
+use nix::sys::socket::sockopt::PeerCredentials;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+
+use provider::AUDIT_TARGET;
+
+const DEFAULT_SOCKET_PATH: &str = "/run/kbs-local-provider.sock";
+const SOCKET_PATH_ENV: &str = "KBS_SOCKET_PATH";
+const PEER_UID_ENV: &str = "KBS_SOCKET_PEER_UID";
+
+/// Listen on a Unix socket and send the resources payload to each client.
+///
+/// If `KBS_SOCKET_PEER_UID` is set, only clients whose `SO_PEERCRED` uid
+/// matches receive the payload; any other client is logged and dropped
+/// without a response.
+///
+/// As with the FIFO, `payload` is called again for every client served and
+/// streams the payload into the connection. A client whose write fails is
+/// dropped with whatever partial payload it received.
+pub fn serve(mut payload: impl FnMut(&mut dyn Write) -> Result<()>) -> Result<()> {
+    let path = std::env::var(SOCKET_PATH_ENV).unwrap_or_else(|_| DEFAULT_SOCKET_PATH.to_string());
+    let path = Path::new(&path);
+
+    let allowed_uid = match std::env::var(PEER_UID_ENV) {
+        Ok(uid) => Some(
+            uid.parse::<u32>()
+                .with_context(|| format!("{PEER_UID_ENV} {uid:?} is not a valid uid"))?,
+        ),
+        Err(_) => None,
+    };
+
+    if path.exists() {
+        fs::remove_file(path)
+            .with_context(|| format!("failed to remove stale socket {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("failed to bind socket at {}", path.display()))?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o666))
+        .with_context(|| format!("failed to set permissions on {}", path.display()))?;
+
+    match allowed_uid {
+        Some(uid) => log::info!("serving CDH resources on socket {} to uid {uid}", path.display()),
+        None => log::info!("serving CDH resources on socket {}", path.display()),
+    }
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::warn!("failed to accept socket connection: {e}");
+                continue;
+            }
+        };
+
+        if let Some(allowed) = allowed_uid {
+            let peer = match getsockopt(&stream, PeerCredentials) {
+                Ok(peer) => peer,
+                Err(e) => {
+                    log::warn!("failed to read socket peer credentials; dropping client: {e}");
+                    continue;
+                }
+            };
+            if peer.uid() != allowed {
+                log::warn!(
+                    "rejected socket client pid {} uid {} (expected uid {allowed})",
+                    peer.pid(),
+                    peer.uid()
+                );
+                continue;
+            }
+        }
+
+        if let Err(e) = payload(&mut stream) {
+            log::warn!("failed to write CDH resources to socket client: {e:#}");
+            continue;
+        }
+        log::info!(target: AUDIT_TARGET, "served CDH resources to socket client");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use anyhow::{Context, Result};
+use provider::crypto::Argon2Params;
+use provider::SecretBytes;
+
+use kbs_local_provider::initdata::ParsedInitData;
+
+const ARGON2_ENV: &str = "KBS_ARGON2";
+
+/// Apply the optional Argon2id pre-stretch to the provider's IKM.
+///
+/// Parameters come from `data.argon2` in init_data, else from `KBS_ARGON2`
+/// (both `m=<KiB>,t=<passes>,p=<lanes>`). Off unless one of them is set. It is
+/// only meaningful for low-entropy, operator-provided IKM; hardware-rooted
+/// IKM does not need it. The init_data digest is the Argon2 salt.
+pub fn apply(ikm: SecretBytes, parsed: &ParsedInitData) -> Result<SecretBytes> {
+    let Some(params) = params(parsed)? else {
+        return Ok(ikm);
+    };
+    log::info!("stretching IKM with Argon2id ({params})");
+    stretch(&ikm, &parsed.init_data_digest, params)
+}
+
+/// The Argon2id parameters [`apply`] uses, or `None` when it is a no-op.
+pub fn params(parsed: &ParsedInitData) -> Result<Option<Argon2Params>> {
+    if let Some(params) = parsed.argon2 {
+        return Ok(Some(params));
+    }
+    std::env::var(ARGON2_ENV)
+        .ok()
+        .map(|value| value.parse().with_context(|| format!("invalid {ARGON2_ENV}")))
+        .transpose()
+}
+
+#[cfg(feature = "argon2")]
+fn stretch(ikm: &[u8], salt: &[u8], params: Argon2Params) -> Result<SecretBytes> {
+    provider::crypto::argon2_stretch(ikm, salt, params)
+}
+
+#[cfg(not(feature = "argon2"))]
+fn stretch(_: &[u8], _: &[u8], _: Argon2Params) -> Result<SecretBytes> {
+    anyhow::bail!(
+        "Argon2 IKM stretching is configured but kbs-local-provider was built without \
+         the argon2 feature"
+    )
+}
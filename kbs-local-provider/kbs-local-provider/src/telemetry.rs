@@ -0,0 +1,27 @@
+use anyhow::{Context, Result};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing_subscriber::layer::SubscriberExt;
+
+/// Export `provider` tracing spans (detection, IKM read, derivation) over
+/// OTLP/HTTP. The endpoint and other exporter settings come from the
+/// standard `OTEL_EXPORTER_OTLP_*` variables.
+///
+/// The returned provider must be kept alive for spans to be exported.
+pub fn init() -> Result<SdkTracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()
+        .context("failed to build OTLP span exporter")?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .build();
+
+    let tracer = provider.tracer("kbs-local-provider");
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::set_global_default(subscriber)
+        .context("failed to install tracing subscriber")?;
+
+    Ok(provider)
+}
@@ -0,0 +1,94 @@
+use anyhow::{Context, Result, bail};
+use provider::SeedProvider;
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const WATCH_AK_ENV: &str = "KBS_WATCH_AK";
+const WATCH_INTERVAL_ENV: &str = "KBS_WATCH_AK_INTERVAL_SECS";
+const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(60);
+/// How often a sleeping watcher checks whether serving has ended.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// Exit status when the IKM changed: EX_TEMPFAIL, i.e. restart and retry.
+const IKM_CHANGED_EXIT_CODE: i32 = 75;
+
+/// SHA-256 of the provider IKM (before any stretch), to compare re-reads
+/// against without keeping the IKM itself around.
+pub fn fingerprint(ikm: &[u8]) -> [u8; 32] {
+    Sha256::digest(ikm).into()
+}
+
+/// Run `serve`, with `KBS_WATCH_AK=1` re-reading the provider IKM every
+/// `KBS_WATCH_AK_INTERVAL_SECS` (default 60) in the background. The re-reads
+/// bypass any provider cache ([`SeedProvider::fresh_ikm`]), so a cached AK
+/// public area cannot hide a replaced AK.
+///
+/// If the AK at the handle was replaced (or anything else mixed into the
+/// IKM changed) the served keys no longer match the actual root, and the
+/// process exits with status 75 so its supervisor restarts it: a fresh
+/// start re-derives the keys and rewrites the public key files as well,
+/// which swapping the payload in place would leave stale. Failed re-reads
+/// are logged and retried at the next interval.
+pub fn while_serving<T>(
+    provider: &dyn SeedProvider,
+    fingerprint: &[u8; 32],
+    serve: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    let Some(interval) = interval_from_env()? else {
+        return serve();
+    };
+    log::info!("re-reading the {} IKM every {interval:?} ({WATCH_AK_ENV}=1)", provider.kind());
+    let stop = AtomicBool::new(false);
+    thread::scope(|scope| {
+        scope.spawn(|| watch(provider, fingerprint, interval, &stop));
+        let result = serve();
+        stop.store(true, Ordering::SeqCst);
+        result
+    })
+}
+
+fn interval_from_env() -> Result<Option<Duration>> {
+    match std::env::var(WATCH_AK_ENV).as_deref() {
+        Err(_) | Ok("0") => return Ok(None),
+        Ok("1") => {}
+        Ok(other) => bail!("unsupported {WATCH_AK_ENV} {other:?} (expected 0 or 1)"),
+    }
+    let Ok(secs) = std::env::var(WATCH_INTERVAL_ENV) else {
+        return Ok(Some(DEFAULT_WATCH_INTERVAL));
+    };
+    let secs: u64 = secs
+        .parse()
+        .with_context(|| format!("{WATCH_INTERVAL_ENV} {secs:?} is not a number of seconds"))?;
+    if secs == 0 {
+        bail!("{WATCH_INTERVAL_ENV} must be at least 1");
+    }
+    Ok(Some(Duration::from_secs(secs)))
+}
+
+fn watch(provider: &dyn SeedProvider, expected: &[u8; 32], interval: Duration, stop: &AtomicBool) {
+    loop {
+        let started = Instant::now();
+        while started.elapsed() < interval {
+            if stop.load(Ordering::SeqCst) {
+                return;
+            }
+            thread::sleep(STOP_POLL_INTERVAL.min(interval));
+        }
+        match provider.fresh_ikm() {
+            Ok(ikm) if provider::crypto::ct_eq_fixed(&fingerprint(&ikm), expected) => {}
+            Ok(_) => {
+                log::error!(
+                    target: provider::AUDIT_TARGET,
+                    "the {} IKM changed while serving (AK replaced?); the served keys are \
+                     stale, exiting for a restart",
+                    provider.kind()
+                );
+                // No destructors run, but the kernel reclaims (and clears)
+                // the process memory, the seeds with it.
+                std::process::exit(IKM_CHANGED_EXIT_CODE);
+            }
+            Err(e) => log::warn!("failed to re-read the {} IKM: {e:#}", provider.kind()),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+#ifndef KBS_PROVIDER_H
+#define KBS_PROVIDER_H
+
+/* Generated by cbindgen from provider/src/ffi.rs; do not edit by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+/*
+ * Success.
+ */
+#define KBS_OK 0
+
+/*
+ * A required pointer was null.
+ */
+#define KBS_ERR_NULL -1
+
+/*
+ * The domain_separator is not valid UTF-8.
+ */
+#define KBS_ERR_UTF8 -2
+
+/*
+ * Derivation failed, e.g. the IKM is too short or too long.
+ */
+#define KBS_ERR_DERIVE -3
+
+/*
+ * An internal error; no output was written.
+ */
+#define KBS_ERR_INTERNAL -4
+
+#ifdef __cplusplus
+extern "C" {
+#endif  // __cplusplus
+
+/*
+ * Derive the 32-byte Ed25519 seed into out_seed, as the Rust
+ * derive_ed25519_seed does: HKDF-SHA256 with the init_data digest as salt
+ * and domain_separator as info.
+ *
+ * Returns KBS_OK, or a negative KBS_ERR_* code on failure.
+ *
+ * # Safety
+ *
+ * - ikm must point to ikm_len readable bytes.
+ * - init_data_digest must point to 32 readable bytes.
+ * - domain_separator must point to a NUL-terminated string.
+ * - out_seed must point to 32 writable bytes not overlapping the inputs.
+ *
+ * All of them must stay valid for the duration of the call.
+ */
+int kbs_derive_ed25519_seed(const uint8_t *ikm,
+                            size_t ikm_len,
+                            const uint8_t *init_data_digest,
+                            const char *domain_separator,
+                            uint8_t *out_seed);
+
+/*
+ * Compute the Ed25519 public key for a 32-byte seed into out_public_key.
+ *
+ * Returns KBS_OK, or a negative KBS_ERR_* code on failure.
+ *
+ * # Safety
+ *
+ * `seed` must point to 32 readable bytes and `out_public_key` to 32
+ * writable bytes, valid for the duration of the call.
+ */
+int kbs_ed25519_public_key(const uint8_t *seed, uint8_t *out_public_key);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* KBS_PROVIDER_H */
@@ -0,0 +1,7 @@
+//! The C interface of `provider` as a shared library, `libkbs_provider.so`.
+//!
+//! The functions are defined in `provider::ffi`; this crate only links them
+//! into a cdylib, so that building `provider` itself never produces one.
+//! C callers include `include/kbs_provider.h`.
+
+pub use provider::ffi::*;
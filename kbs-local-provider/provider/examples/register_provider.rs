@@ -0,0 +1,48 @@
+//! Register an out-of-tree seed provider and check that detection picks it.
+//!
+//! Run with `cargo run -p provider --example register_provider`.
+
+use anyhow::{Result, ensure};
+use provider::{ProviderKind, SecretBytes, SeedProvider};
+
+const DUMMY_IKM: &[u8] = b"dummy-tee-ikm-for-examples-only";
+
+/// Stand-in for a proprietary TEE; returns fixed IKM.
+struct DummyProvider;
+
+impl SeedProvider for DummyProvider {
+    fn ikm(&self) -> Result<SecretBytes> {
+        Ok(SecretBytes::new(DUMMY_IKM.to_vec()))
+    }
+
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Custom("dummy")
+    }
+}
+
+fn main() -> Result<()> {
+    provider::register_provider("dummy", || true, || Ok(Box::new(DummyProvider)));
+
+    let report = provider::diagnose();
+    ensure!(
+        report.checks.first().map(|c| c.kind) == Some(ProviderKind::Custom("dummy")),
+        "registered provider missing from diagnostics"
+    );
+    println!("{report}");
+
+    let provider = provider::detect_provider()?;
+    let ikm = provider.ikm()?;
+    ensure!(
+        provider.kind() == ProviderKind::Custom("dummy") && ikm.as_slice() == DUMMY_IKM,
+        "detection did not pick the registered provider"
+    );
+    let seed = provider::crypto::derive_ed25519_seed(&ikm, &[0u8; 32], "example")?;
+    let public_key = provider::crypto::ed25519_public_key(&seed);
+    println!("derived public key from dummy provider: {}", hex(&public_key));
+
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
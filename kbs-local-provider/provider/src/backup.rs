@@ -0,0 +1,87 @@
+//! Passphrase-encrypted backups of derived key material, for disaster
+//! recovery when losing the TPM must not mean losing the identity.
+//!
+//! A backup trades away the TEE binding: whoever has the file and the
+//! passphrase has the keys, on any machine, without attestation. Keep the
+//! passphrase offline and the file out of reach of the workload.
+
+use anyhow::{Result, anyhow, bail};
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305, XNonce};
+use zeroize::Zeroizing;
+
+use crate::crypto::Argon2Params;
+use crate::secrets::SecretBytes;
+
+/// Leads every backup, naming the format version.
+const MAGIC: &[u8; 8] = b"KBSBAK1\0";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+/// Magic, the three Argon2 parameters (big-endian u32s), salt and nonce.
+const HEADER_LEN: usize = MAGIC.len() + 12 + SALT_LEN + NONCE_LEN;
+
+/// Encrypt `secret` under `passphrase`: XChaCha20-Poly1305 with a key from
+/// Argon2id(`passphrase`, random salt) at the cost in `params`.
+///
+/// The blob is `KBSBAK1\0 || m || t || p || salt || nonce || ciphertext`,
+/// the header authenticated as associated data, so [`open`] needs nothing
+/// but the passphrase.
+pub fn seal(secret: &[u8], passphrase: &[u8], params: Argon2Params) -> Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    for value in [params.memory_kib, params.iterations, params.parallelism] {
+        header.extend_from_slice(&value.to_be_bytes());
+    }
+    let mut random = [0u8; SALT_LEN + NONCE_LEN];
+    getrandom::getrandom(&mut random)
+        .map_err(|err| anyhow!("failed to generate a backup salt and nonce: {err}"))?;
+    header.extend_from_slice(&random);
+    let (salt, nonce) = random.split_at(SALT_LEN);
+
+    let cipher = cipher(passphrase, salt, params)?;
+    let ciphertext = cipher
+        .encrypt(XNonce::from_slice(nonce), Payload { msg: secret, aad: &header })
+        .map_err(|_| anyhow!("failed to encrypt the backup"))?;
+    header.extend_from_slice(&ciphertext);
+    Ok(header)
+}
+
+/// Decrypt a backup written by [`seal`].
+pub fn open(blob: &[u8], passphrase: &[u8]) -> Result<SecretBytes> {
+    if blob.len() < HEADER_LEN || !blob.starts_with(MAGIC) {
+        bail!("not a kbs-local-provider backup (or an unsupported version)");
+    }
+    let (header, ciphertext) = blob.split_at(HEADER_LEN);
+    let word = |i: usize| {
+        let at = MAGIC.len() + 4 * i;
+        u32::from_be_bytes(header[at..at + 4].try_into().expect("4-byte slice"))
+    };
+    let params = Argon2Params {
+        memory_kib: word(0),
+        iterations: word(1),
+        parallelism: word(2),
+    };
+    let (salt, nonce) = header[MAGIC.len() + 12..].split_at(SALT_LEN);
+
+    let cipher = cipher(passphrase, salt, params)?;
+    let plaintext = cipher
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+        .map_err(|_| anyhow!("failed to decrypt the backup (wrong passphrase or corrupt file)"))?;
+    Ok(SecretBytes::new(plaintext))
+}
+
+fn cipher(passphrase: &[u8], salt: &[u8], params: Argon2Params) -> Result<XChaCha20Poly1305> {
+    use argon2::{Algorithm, Argon2, Params, Version};
+
+    if passphrase.is_empty() {
+        bail!("the backup passphrase is empty");
+    }
+    let cost = Params::new(params.memory_kib, params.iterations, params.parallelism, Some(32))
+        .map_err(|err| anyhow!("invalid Argon2 parameters {params}: {err}"))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, cost)
+        .hash_password_into(passphrase, salt, key.as_mut())
+        .map_err(|err| anyhow!("Argon2 key derivation failed: {err}"))?;
+    XChaCha20Poly1305::new_from_slice(key.as_ref())
+        .map_err(|_| anyhow!("invalid backup key length"))
+}
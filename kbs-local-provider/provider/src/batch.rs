@@ -0,0 +1,186 @@
+use anyhow::{Result, bail};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::crypto::{self, Kdf};
+use crate::info_template::InfoTemplate;
+use crate::secrets::SecretBytes;
+
+/// Kind of key [`derive_keys`] derives for a [`KeySpec`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyType {
+    /// A 32-byte Ed25519 seed, identical to [`crypto::derive_ed25519_seed`]
+    /// with the spec's label as domain_separator.
+    Ed25519,
+    /// A raw AES-256 key, as [`crypto::derive_aes256_key`]. It has no
+    /// public half.
+    Aes256,
+    /// A P-256 scalar, as [`crypto::derive_p256_secret`].
+    #[cfg(feature = "p256")]
+    P256,
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            KeyType::Ed25519 => "ed25519",
+            KeyType::Aes256 => "aes256",
+            #[cfg(feature = "p256")]
+            KeyType::P256 => "p256",
+        })
+    }
+}
+
+/// One key for [`derive_keys`]: a resource path, a key type and an
+/// optional index for several keys of one type under the same resource.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeySpec {
+    pub resource: String,
+    pub key_type: KeyType,
+    pub index: Option<u32>,
+}
+
+impl KeySpec {
+    pub fn new(resource: impl Into<String>, key_type: KeyType) -> Self {
+        Self {
+            resource: resource.into(),
+            key_type,
+            index: None,
+        }
+    }
+
+    pub fn with_index(mut self, index: u32) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// The domain_separator the key is derived under, which is also its
+    /// key in the [`derive_keys`] map: `<resource>[#<index>]`, with the
+    /// crypto helpers' `:aes256`/`:p256` suffix for those types.
+    ///
+    /// An Ed25519 key without index uses the bare resource path, so it
+    /// matches an init_data key declared with that domain_separator.
+    /// Resources may not contain `#` or `:`, which keeps labels of distinct
+    /// specs distinct.
+    pub fn label(&self) -> Result<String> {
+        if self.resource.is_empty() {
+            bail!("key spec has an empty resource path");
+        }
+        if self.resource.contains(['#', ':']) {
+            bail!("key spec resource {:?} contains '#' or ':'", self.resource);
+        }
+        let base = self.base();
+        Ok(match self.key_type {
+            KeyType::Ed25519 => base,
+            other => format!("{base}:{other}"),
+        })
+    }
+
+    /// The label without the key type suffix, as passed to the crypto
+    /// helpers that append it themselves.
+    fn base(&self) -> String {
+        match self.index {
+            Some(index) => format!("{}#{index}", self.resource),
+            None => self.resource.clone(),
+        }
+    }
+}
+
+/// A key derived by [`derive_keys`].
+#[derive(Debug)]
+pub struct DerivedKey {
+    /// The secret key material (seed, key or scalar), zeroized on drop.
+    pub secret: SecretBytes,
+    /// The public key: raw 32 bytes for Ed25519, uncompressed SEC1 for
+    /// P-256, `None` for symmetric keys.
+    pub public_key: Option<Vec<u8>>,
+}
+
+/// Derive every key in `specs` from `ikm`, salted with the init_data digest,
+/// in one call. The programmatic counterpart to multiple init_data keys.
+///
+/// The result maps each spec's [`KeySpec::label`] to its key. Two specs
+/// with the same label (same resource, type and index) are rejected rather
+/// than silently deriving the same key twice.
+pub fn derive_keys(
+    ikm: &[u8],
+    init_data_digest: &[u8; 32],
+    specs: &[KeySpec],
+) -> Result<HashMap<String, DerivedKey>> {
+    derive(ikm, init_data_digest, specs, None)
+}
+
+/// Like [`derive_keys`], but with the HKDF info built from `template`
+/// (`{prefix}` is empty, `{domain_separator}` is the resource path), for
+/// interop with a KBS that constructs its info differently.
+///
+/// The rendered info is used as is, without the `:aes256`/`:p256` suffixes
+/// (P-256 only adds its retry counter), so even the default template
+/// derives other AES-256 and P-256 keys than [`derive_keys`]. Specs whose
+/// info would collide under the template are rejected. The map is still
+/// keyed by [`KeySpec::label`].
+pub fn derive_keys_with_template(
+    ikm: &[u8],
+    init_data_digest: &[u8; 32],
+    specs: &[KeySpec],
+    template: &InfoTemplate,
+) -> Result<HashMap<String, DerivedKey>> {
+    derive(ikm, init_data_digest, specs, Some(template))
+}
+
+fn derive(
+    ikm: &[u8],
+    init_data_digest: &[u8; 32],
+    specs: &[KeySpec],
+    template: Option<&InfoTemplate>,
+) -> Result<HashMap<String, DerivedKey>> {
+    let salt = Some(init_data_digest.as_slice());
+    let mut keys = HashMap::with_capacity(specs.len());
+    let mut infos = HashSet::with_capacity(specs.len());
+    for spec in specs {
+        let label = spec.label()?;
+        if keys.contains_key(&label) {
+            bail!("key spec {label:?} is listed more than once");
+        }
+        // Without a template the info is the label, which is exactly what
+        // derive_aes256_key expands; derive_p256_secret adds the suffix itself.
+        let info = match (template, spec.key_type) {
+            (Some(template), key_type) => {
+                template.render("", &spec.resource, &key_type.to_string(), spec.index)
+            }
+            #[cfg(feature = "p256")]
+            (None, KeyType::P256) => spec.base(),
+            (None, _) => label.clone(),
+        };
+        if !infos.insert(info.clone()) {
+            bail!("key spec {label:?} collides with another one in HKDF info {info:?}");
+        }
+        let key = match spec.key_type {
+            KeyType::Ed25519 => {
+                let seed = crypto::derive_key(Kdf::HkdfSha256, ikm, salt, &info, 32)?;
+                let seed_array: &[u8; 32] =
+                    seed.as_slice().try_into().expect("derived a 32-byte seed");
+                let public_key = crypto::ed25519_public_key(seed_array).to_vec();
+                DerivedKey {
+                    secret: seed,
+                    public_key: Some(public_key),
+                }
+            }
+            KeyType::Aes256 => DerivedKey {
+                secret: crypto::derive_key(Kdf::HkdfSha256, ikm, salt, &info, 32)?,
+                public_key: None,
+            },
+            #[cfg(feature = "p256")]
+            KeyType::P256 => {
+                let secret = crypto::derive_p256_secret(ikm, salt, &info)?;
+                let public_key = crypto::p256_public_key(&secret)?.to_vec();
+                DerivedKey {
+                    secret: SecretBytes::new(secret.to_vec()),
+                    public_key: Some(public_key),
+                }
+            }
+        };
+        keys.insert(label, key);
+    }
+    Ok(keys)
+}
@@ -0,0 +1,76 @@
+use std::fmt;
+
+use crate::ProviderKind;
+
+/// Outcome of one provider's detection check.
+pub struct ProviderCheck {
+    pub kind: ProviderKind,
+    /// Whether the provider's cargo feature is enabled in this build.
+    pub compiled: bool,
+    /// What the provider's `detect_platform()` check saw.
+    pub detail: String,
+}
+
+/// Per-provider detection diagnostics, in detection order.
+///
+/// Only device and capability presence is reported; no key material is
+/// read, so the report is safe to log.
+pub struct DetectionReport {
+    pub checks: Vec<ProviderCheck>,
+}
+
+impl fmt::Display for DetectionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, check) in self.checks.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            if check.compiled {
+                write!(f, "{}: {}", check.kind, check.detail)?;
+            } else {
+                write!(f, "{}: not compiled in", check.kind)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run every provider's detection check and report what each one saw.
+pub fn diagnose() -> DetectionReport {
+    let mut checks = crate::registry::checks();
+    checks.extend([tpm_check(), keyring_check(), hardware_check()]);
+    DetectionReport { checks }
+}
+
+fn tpm_check() -> ProviderCheck {
+    ProviderCheck {
+        kind: ProviderKind::Tpm,
+        compiled: cfg!(feature = "tpm-provider"),
+        #[cfg(feature = "tpm-provider")]
+        detail: crate::tpm::detection_status(),
+        #[cfg(not(feature = "tpm-provider"))]
+        detail: String::new(),
+    }
+}
+
+fn keyring_check() -> ProviderCheck {
+    ProviderCheck {
+        kind: ProviderKind::Keyring,
+        compiled: cfg!(feature = "keyring"),
+        #[cfg(feature = "keyring")]
+        detail: crate::keyring::detection_status(),
+        #[cfg(not(feature = "keyring"))]
+        detail: String::new(),
+    }
+}
+
+fn hardware_check() -> ProviderCheck {
+    ProviderCheck {
+        kind: ProviderKind::Hardware,
+        compiled: cfg!(feature = "hardware"),
+        #[cfg(feature = "hardware")]
+        detail: crate::hardware::detection_status(),
+        #[cfg(not(feature = "hardware"))]
+        detail: String::new(),
+    }
+}
@@ -0,0 +1,105 @@
+//! C interface to the seed derivation, for non-Rust verifiers and agents
+//! that need to cross-check the TEE identity.
+//!
+//! The provider-ffi crate links this module into the shared library
+//! `libkbs_provider.so`; C callers include its `include/kbs_provider.h`,
+//! which cbindgen generates from this module. The doc comments are copied
+//! into the header, so they are written for C readers.
+//!
+//! Every buffer belongs to the caller: nothing is allocated or freed here,
+//! and no pointer is retained after a call returns. Output buffers are only
+//! written on success. Callers should wipe seed buffers once done with them.
+
+use std::ffi::{CStr, c_char, c_int};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+use crate::crypto;
+
+/// Success.
+pub const KBS_OK: c_int = 0;
+/// A required pointer was null.
+pub const KBS_ERR_NULL: c_int = -1;
+/// The domain_separator is not valid UTF-8.
+pub const KBS_ERR_UTF8: c_int = -2;
+/// Derivation failed, e.g. the IKM is too short or too long.
+pub const KBS_ERR_DERIVE: c_int = -3;
+/// An internal error; no output was written.
+pub const KBS_ERR_INTERNAL: c_int = -4;
+
+/// Derive the 32-byte Ed25519 seed into out_seed, as the Rust
+/// derive_ed25519_seed does: HKDF-SHA256 with the init_data digest as salt
+/// and domain_separator as info.
+///
+/// Returns KBS_OK, or a negative KBS_ERR_* code on failure.
+///
+/// # Safety
+///
+/// - ikm must point to ikm_len readable bytes.
+/// - init_data_digest must point to 32 readable bytes.
+/// - domain_separator must point to a NUL-terminated string.
+/// - out_seed must point to 32 writable bytes not overlapping the inputs.
+///
+/// All of them must stay valid for the duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn kbs_derive_ed25519_seed(
+    ikm: *const u8,
+    ikm_len: usize,
+    init_data_digest: *const u8,
+    domain_separator: *const c_char,
+    out_seed: *mut u8,
+) -> c_int {
+    if ikm.is_null()
+        || init_data_digest.is_null()
+        || domain_separator.is_null()
+        || out_seed.is_null()
+    {
+        return KBS_ERR_NULL;
+    }
+    // SAFETY: non-null, and valid for these lengths per the caller contract.
+    let (ikm, digest, domain_separator) = unsafe {
+        (
+            std::slice::from_raw_parts(ikm, ikm_len),
+            &*init_data_digest.cast::<[u8; 32]>(),
+            CStr::from_ptr(domain_separator),
+        )
+    };
+    let Ok(domain_separator) = domain_separator.to_str() else {
+        return KBS_ERR_UTF8;
+    };
+    let derived = catch_unwind(AssertUnwindSafe(|| {
+        crypto::derive_ed25519_seed(ikm, digest, domain_separator)
+    }));
+    match derived {
+        Ok(Ok(seed)) => {
+            // SAFETY: out_seed is non-null and has room for 32 bytes per the
+            // caller contract.
+            unsafe { std::ptr::copy_nonoverlapping(seed.as_ptr(), out_seed, 32) };
+            KBS_OK
+        }
+        Ok(Err(_)) => KBS_ERR_DERIVE,
+        Err(_) => KBS_ERR_INTERNAL,
+    }
+}
+
+/// Compute the Ed25519 public key for a 32-byte seed into out_public_key.
+///
+/// Returns KBS_OK, or a negative KBS_ERR_* code on failure.
+///
+/// # Safety
+///
+/// `seed` must point to 32 readable bytes and `out_public_key` to 32
+/// writable bytes, valid for the duration of the call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn kbs_ed25519_public_key(seed: *const u8, out_public_key: *mut u8) -> c_int {
+    if seed.is_null() || out_public_key.is_null() {
+        return KBS_ERR_NULL;
+    }
+    // SAFETY: non-null and 32 bytes long per the caller contract.
+    let seed = unsafe { &*seed.cast::<[u8; 32]>() };
+    let Ok(public_key) = catch_unwind(|| crypto::ed25519_public_key(seed)) else {
+        return KBS_ERR_INTERNAL;
+    };
+    // SAFETY: out_public_key is non-null and has room for 32 bytes.
+    unsafe { std::ptr::copy_nonoverlapping(public_key.as_ptr(), out_public_key, 32) };
+    KBS_OK
+}
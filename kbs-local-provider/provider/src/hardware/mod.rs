@@ -0,0 +1,110 @@
+use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+
+use crate::{ProviderInfo, ProviderKind, SecretBytes, SeedProvider};
+
+const SECRET_PATH_ENV: &str = "KBS_HARDWARE_SECRET";
+const DMI_ID_DIR: &str = "/sys/class/dmi/id";
+/// SMBIOS fields mixed into the IKM, in this order.
+const DMI_FIELDS: [&str; 2] = ["product_uuid", "board_serial"];
+/// Leads the IKM so it cannot collide with another provider's encoding.
+const IKM_CONTEXT: &[u8] = b"kbs-local-provider hardware ikm v1\0";
+
+/// Check if the operator secret named by `KBS_HARDWARE_SECRET` exists.
+///
+/// Without it the provider never activates, however stable the SMBIOS data.
+pub fn detect_platform() -> bool {
+    HardwareSeedProvider::from_env().is_some_and(|p| p.secret_path.is_file())
+}
+
+/// Describe what `detect_platform` sees, for diagnostics.
+pub fn detection_status() -> String {
+    let Some(provider) = HardwareSeedProvider::from_env() else {
+        return format!("{SECRET_PATH_ENV} not set");
+    };
+    let path = provider.secret_path.display();
+    if provider.secret_path.is_file() {
+        format!("secret {path}: present")
+    } else {
+        format!("secret {path}: not found")
+    }
+}
+
+/// SMBIOS-plus-secret seed provider, a fallback for hosts with no TEE.
+///
+/// The IKM combines stable hardware identifiers from `/sys/class/dmi/id`
+/// (`product_uuid`, `board_serial`) with an operator-provisioned secret
+/// file. This is NOT a hardware root of trust: SMBIOS data is neither
+/// secret nor attested, so the keys are only as safe as the secret file.
+/// It merely ties them to one machine as well. Use it for non-TEE
+/// fallback deployments, never where a TPM or TEE is available.
+pub struct HardwareSeedProvider {
+    secret_path: PathBuf,
+}
+
+impl HardwareSeedProvider {
+    /// Build a provider for the secret file named by `KBS_HARDWARE_SECRET`.
+    pub fn from_env() -> Option<Self> {
+        std::env::var_os(SECRET_PATH_ENV).map(Self::new)
+    }
+
+    pub fn new(secret_path: impl Into<PathBuf>) -> Self {
+        Self {
+            secret_path: secret_path.into(),
+        }
+    }
+}
+
+/// Append `field` to `ikm` with a big-endian u32 length prefix.
+fn push_field(ikm: &mut Vec<u8>, field: &[u8]) {
+    let len = u32::try_from(field.len()).expect("IKM fields are small files");
+    ikm.extend_from_slice(&len.to_be_bytes());
+    ikm.extend_from_slice(field);
+}
+
+fn read_dmi_field(name: &str) -> Result<Vec<u8>> {
+    let path = Path::new(DMI_ID_DIR).join(name);
+    let value = std::fs::read(&path)
+        .with_context(|| format!("failed to read SMBIOS field {}", path.display()))?;
+    let value = value.trim_ascii();
+    if value.is_empty() {
+        bail!("SMBIOS field {} is empty", path.display());
+    }
+    Ok(value.to_vec())
+}
+
+impl SeedProvider for HardwareSeedProvider {
+    #[cfg_attr(feature = "otel", tracing::instrument(skip_all, fields(provider = "hardware")))]
+    fn ikm(&self) -> Result<SecretBytes> {
+        let secret = SecretBytes::new(std::fs::read(&self.secret_path).with_context(|| {
+            format!("failed to read hardware secret {}", self.secret_path.display())
+        })?);
+        if secret.is_empty() {
+            bail!("hardware secret {} is empty", self.secret_path.display());
+        }
+
+        let mut ikm = SecretBytes::new(IKM_CONTEXT.to_vec());
+        for name in DMI_FIELDS {
+            push_field(&mut ikm, &read_dmi_field(name)?);
+        }
+        push_field(&mut ikm, &secret);
+
+        log::warn!(
+            "using SMBIOS and {} as IKM; this is not a hardware root of trust",
+            self.secret_path.display()
+        );
+        Ok(ikm)
+    }
+
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Hardware
+    }
+
+    fn info(&self) -> Result<ProviderInfo> {
+        Ok(ProviderInfo {
+            kind: self.kind().to_string(),
+            algorithm: None,
+            source_handle: Some(self.secret_path.display().to_string()),
+        })
+    }
+}
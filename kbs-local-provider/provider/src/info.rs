@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Public description of how a provider's seed is rooted, for embedding in
+/// attestation or claims documents so verifiers can key policy on it.
+///
+/// This never carries key material. The field names are a stable schema.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderInfo {
+    /// Provider kind, as printed by `ProviderKind` (`tpm`, `keyring`, ...).
+    pub kind: String,
+    /// What the IKM is rooted in, e.g. `rsa` for the TPM AK, when known.
+    pub algorithm: Option<String>,
+    /// Handle the IKM is read from, as `0x`-prefixed hex, when known.
+    pub source_handle: Option<String>,
+}
+
+impl ProviderInfo {
+    /// Render as a single-line JSON object.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self).context("failed to serialize provider info")
+    }
+
+    /// Parse the JSON produced by [`ProviderInfo::to_json`].
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("failed to parse provider info")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_schema_is_stable() {
+        let info = ProviderInfo {
+            kind: "tpm".to_string(),
+            algorithm: Some("rsa".to_string()),
+            source_handle: Some("0x81000002".to_string()),
+        };
+        let json = info.to_json().unwrap();
+        assert_eq!(json, r#"{"kind":"tpm","algorithm":"rsa","source_handle":"0x81000002"}"#);
+        assert_eq!(ProviderInfo::from_json(&json).unwrap(), info);
+    }
+
+    #[test]
+    fn unknown_fields_serialize_as_null() {
+        let info = ProviderInfo {
+            kind: "hardware".to_string(),
+            algorithm: None,
+            source_handle: None,
+        };
+        let json = info.to_json().unwrap();
+        assert_eq!(json, r#"{"kind":"hardware","algorithm":null,"source_handle":null}"#);
+        assert_eq!(ProviderInfo::from_json(&json).unwrap(), info);
+    }
+}
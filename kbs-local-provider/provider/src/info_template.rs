@@ -0,0 +1,111 @@
+use anyhow::{Result, bail};
+use std::fmt;
+use std::str::FromStr;
+
+/// A template for the HKDF info, to match the exact info construction of an
+/// external KBS, e.g. `{prefix}:{domain_separator}:{key_type}:{index}`.
+///
+/// Placeholders are `{prefix}`, `{domain_separator}`, `{key_type}` and
+/// `{index}` (empty for a key without index); everything else is literal.
+/// Any other `{...}` and unmatched braces are rejected, and
+/// `{domain_separator}` is required so distinct keys get distinct info.
+///
+/// The default, `{prefix}{domain_separator}`, is the info used without a
+/// template. Every other template derives different keys: a consumer
+/// re-deriving or verifying them must use the very same one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InfoTemplate {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Prefix,
+    DomainSeparator,
+    KeyType,
+    Index,
+}
+
+impl InfoTemplate {
+    /// The HKDF info for one key.
+    pub fn render(
+        &self,
+        prefix: &str,
+        domain_separator: &str,
+        key_type: &str,
+        index: Option<u32>,
+    ) -> String {
+        let mut info = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => info.push_str(literal),
+                Part::Prefix => info.push_str(prefix),
+                Part::DomainSeparator => info.push_str(domain_separator),
+                Part::KeyType => info.push_str(key_type),
+                Part::Index => {
+                    if let Some(index) = index {
+                        info.push_str(&index.to_string());
+                    }
+                }
+            }
+        }
+        info
+    }
+}
+
+impl Default for InfoTemplate {
+    fn default() -> Self {
+        "{prefix}{domain_separator}"
+            .parse()
+            .expect("the default info template is valid")
+    }
+}
+
+impl FromStr for InfoTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(open) = rest.find(['{', '}']) {
+            if rest[open..].starts_with('}') {
+                bail!("info template {s:?} has an unmatched '}}'");
+            }
+            if open > 0 {
+                parts.push(Part::Literal(rest[..open].to_string()));
+            }
+            let Some(len) = rest[open..].find('}') else {
+                bail!("info template {s:?} has an unmatched '{{'");
+            };
+            parts.push(match &rest[open + 1..open + len] {
+                "prefix" => Part::Prefix,
+                "domain_separator" => Part::DomainSeparator,
+                "key_type" => Part::KeyType,
+                "index" => Part::Index,
+                other => bail!(
+                    "unknown placeholder {{{other}}} in info template {s:?} (expected \
+                     prefix, domain_separator, key_type or index)"
+                ),
+            });
+            rest = &rest[open + len + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        if !parts.contains(&Part::DomainSeparator) {
+            bail!("info template {s:?} lacks {{domain_separator}}");
+        }
+        Ok(Self {
+            source: s.to_string(),
+            parts,
+        })
+    }
+}
+
+impl fmt::Display for InfoTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::{Result, anyhow, bail};
+use linux_keyutils::{Key, KeyRing, KeyRingIdentifier};
+
+use crate::{ProviderInfo, ProviderKind, SecretBytes, SeedProvider};
+
+const KEY_NAME_ENV: &str = "KBS_KEYRING_KEY";
+const KEY_TYPE_ENV: &str = "KBS_KEYRING_KEY_TYPE";
+/// The only key type `linux-keyutils` searches for.
+const SUPPORTED_KEY_TYPE: &str = "user";
+
+/// Check if the configured key is present in the session or user keyring.
+pub fn detect_platform() -> bool {
+    KeyringSeedProvider::from_env().is_some_and(|p| p.search().is_ok())
+}
+
+/// Describe what `detect_platform` sees, for diagnostics.
+pub fn detection_status() -> String {
+    let Ok(name) = std::env::var(KEY_NAME_ENV) else {
+        return format!("{KEY_NAME_ENV} not set");
+    };
+    if let Err(e) = check_key_type() {
+        return format!("key {name:?}: {e:#}");
+    }
+    match KeyringSeedProvider::from_env().map(|p| p.search()) {
+        Some(Ok(_)) => format!("key {name:?}: present"),
+        Some(Err(_)) => format!("key {name:?}: not found in session or user keyring"),
+        None => format!("key {name:?}: invalid key name"),
+    }
+}
+
+/// `KBS_KEYRING_KEY_TYPE`, if set, must name the one supported key type,
+/// so a configuration written for another type fails loudly instead of
+/// silently falling through to the next provider.
+fn check_key_type() -> Result<()> {
+    match std::env::var(KEY_TYPE_ENV) {
+        Ok(key_type) if key_type != SUPPORTED_KEY_TYPE => {
+            bail!("unsupported {KEY_TYPE_ENV} {key_type:?} (expected {SUPPORTED_KEY_TYPE:?})")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Kernel keyring seed provider.
+///
+/// Looks up a named `user` key in the session keyring, then the user
+/// keyring, through `linux-keyutils`, and returns its payload as input
+/// keying material. To root it in a TPM-sealed key, load the unsealed
+/// payload into a `user` key at boot.
+pub struct KeyringSeedProvider {
+    description: String,
+}
+
+impl KeyringSeedProvider {
+    /// Build a provider for the key named by `KBS_KEYRING_KEY`; `None` when
+    /// it is unset or `KBS_KEYRING_KEY_TYPE` names an unsupported type.
+    pub fn from_env() -> Option<Self> {
+        let description = std::env::var(KEY_NAME_ENV).ok()?;
+        check_key_type().ok()?;
+        Self::new(&description).ok()
+    }
+
+    pub fn new(description: &str) -> Result<Self> {
+        if description.contains('\0') {
+            bail!("key name contains a NUL byte");
+        }
+        Ok(Self { description: description.to_string() })
+    }
+
+    fn search(&self) -> Result<Key> {
+        for keyring in [KeyRingIdentifier::Session, KeyRingIdentifier::User] {
+            let Ok(keyring) = KeyRing::from_special_id(keyring, false) else {
+                continue;
+            };
+            if let Ok(key) = keyring.search(&self.description) {
+                return Ok(key);
+            }
+        }
+        bail!(
+            "{SUPPORTED_KEY_TYPE} key {:?} not found in session or user keyring",
+            self.description
+        )
+    }
+}
+
+impl SeedProvider for KeyringSeedProvider {
+    #[cfg_attr(feature = "otel", tracing::instrument(skip_all, fields(provider = "keyring")))]
+    fn ikm(&self) -> Result<SecretBytes> {
+        let key = self.search()?;
+        let payload = SecretBytes::new(
+            key.read_to_vec()
+                .map_err(|e| anyhow!("failed to read keyring key payload: {e:?}"))?,
+        );
+
+        if payload.is_empty() {
+            bail!("keyring key {:?} has an empty payload", self.description);
+        }
+
+        log::info!("read keyring key {:?} ({} bytes)", self.description, payload.len());
+        Ok(payload)
+    }
+
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Keyring
+    }
+
+    fn info(&self) -> Result<ProviderInfo> {
+        Ok(ProviderInfo {
+            kind: self.kind().to_string(),
+            algorithm: Some(SUPPORTED_KEY_TYPE.to_string()),
+            source_handle: Some(format!("{:#010x}", self.search()?.get_id().as_raw_id())),
+        })
+    }
+}
@@ -1,30 +1,204 @@
+#[cfg(feature = "backup")]
+pub mod backup;
+mod batch;
 pub mod crypto;
+mod diagnostics;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod info;
+mod info_template;
+pub mod platform;
+mod registry;
+pub mod secrets;
 
+#[cfg(feature = "hardware")]
+pub mod hardware;
+#[cfg(feature = "keyring")]
+pub mod keyring;
 #[cfg(feature = "tpm-provider")]
 pub mod tpm;
 
 use anyhow::Result;
-use zeroize::Zeroizing;
+use std::fmt;
+use std::sync::OnceLock;
+
+pub use batch::{DerivedKey, KeySpec, KeyType, derive_keys, derive_keys_with_template};
+pub use diagnostics::{DetectionReport, ProviderCheck, diagnose};
+pub use info::ProviderInfo;
+pub use info_template::InfoTemplate;
+pub use registry::{BuildFn, DetectFn, register_provider};
+pub use secrets::{SecretBytes, SecretString};
+
+/// `log` target for the lifecycle of secrets: IKM reads, derivations,
+/// serving and zeroization. Route it on its own with
+/// `RUST_LOG=kbs_audit=info` (or `=trace` to include zeroization).
+///
+/// Only lifecycle events, counts and non-secret identifiers (resource
+/// paths, handles, lengths) are ever logged on it, never secret values.
+pub const AUDIT_TARGET: &str = "kbs_audit";
+
+/// Seed provider implementations known to this crate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProviderKind {
+    Tpm,
+    Keyring,
+    /// SMBIOS identifiers plus an operator secret; not a hardware root of trust.
+    Hardware,
+    /// An out-of-tree provider added with `register_provider`.
+    Custom(&'static str),
+}
+
+impl ProviderKind {
+    /// Whether the IKM is rooted in attestable TEE hardware (currently only
+    /// the TPM AK). The keyring, SMBIOS fallback and out-of-tree providers
+    /// are not, whatever they wrap.
+    pub fn is_hardware_rooted(self) -> bool {
+        matches!(self, ProviderKind::Tpm)
+    }
+}
+
+impl fmt::Display for ProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProviderKind::Tpm => "tpm",
+            ProviderKind::Keyring => "keyring",
+            ProviderKind::Hardware => "hardware",
+            ProviderKind::Custom(name) => name,
+        })
+    }
+}
 
 /// Trait for TEE-specific seed providers.
 ///
 /// Each provider returns input keying material (IKM) that is fed into
 /// HKDF-SHA256 together with the init_data digest and domain separator
 /// to derive a deterministic Ed25519 seed.
-pub trait SeedProvider {
+pub trait SeedProvider: Send + Sync {
     /// Return the input keying material for HKDF seed derivation.
-    fn ikm(&self) -> Result<Zeroizing<Vec<u8>>>;
+    fn ikm(&self) -> Result<SecretBytes>;
+
+    /// Re-read the IKM from its source, bypassing any cache the provider
+    /// keeps, to notice the root changing underneath a running process.
+    /// Providers with a cache should override this.
+    fn fresh_ikm(&self) -> Result<SecretBytes> {
+        self.ikm()
+    }
+
+    /// Which kind of provider this is, for logs and metadata.
+    fn kind(&self) -> ProviderKind;
+
+    /// Public description of where the seed is rooted. Providers that know
+    /// more than their kind should override this.
+    fn info(&self) -> Result<ProviderInfo> {
+        Ok(ProviderInfo {
+            kind: self.kind().to_string(),
+            algorithm: None,
+            source_handle: None,
+        })
+    }
+}
+
+/// Built-in providers whose cargo features are enabled in this build, in
+/// detection order. Registered (out-of-tree) providers are not included.
+pub fn compiled_providers() -> &'static [ProviderKind] {
+    const COMPILED: &[ProviderKind] = &[
+        #[cfg(feature = "tpm-provider")]
+        ProviderKind::Tpm,
+        #[cfg(feature = "keyring")]
+        ProviderKind::Keyring,
+        #[cfg(feature = "hardware")]
+        ProviderKind::Hardware,
+    ];
+    COMPILED
 }
 
 /// Detect the available seed provider and return it.
 ///
-/// Detection order: TPM → (TDX in the future) → error.
+/// Detection order: registered providers → TPM → kernel keyring →
+/// SMBIOS + secret file → (TDX in the future) → error.
+#[cfg_attr(
+    feature = "otel",
+    tracing::instrument(skip_all, fields(provider = tracing::field::Empty))
+)]
 pub fn detect_provider() -> Result<Box<dyn SeedProvider>> {
+    if let Some(detected) = registry::detect() {
+        let (kind, provider) = detected?;
+        log::info!("detected registered seed provider {kind}");
+        #[cfg(feature = "otel")]
+        record_kind(kind);
+        return Ok(provider);
+    }
+
     #[cfg(feature = "tpm-provider")]
     if tpm::detect_platform() {
         log::info!("detected TPM seed provider");
+        #[cfg(feature = "otel")]
+        record_kind(ProviderKind::Tpm);
         return Ok(Box::new(tpm::TpmSeedProvider::default()));
     }
 
-    anyhow::bail!("no seed provider detected")
+    #[cfg(feature = "keyring")]
+    if let Some(provider) = keyring::KeyringSeedProvider::from_env()
+        && keyring::detect_platform()
+    {
+        log::info!("detected kernel keyring seed provider");
+        #[cfg(feature = "otel")]
+        record_kind(ProviderKind::Keyring);
+        return Ok(Box::new(provider));
+    }
+
+    #[cfg(feature = "hardware")]
+    if let Some(provider) = hardware::HardwareSeedProvider::from_env()
+        && hardware::detect_platform()
+    {
+        log::warn!("detected SMBIOS + secret file seed provider (not a hardware root of trust)");
+        #[cfg(feature = "otel")]
+        record_kind(ProviderKind::Hardware);
+        return Ok(Box::new(provider));
+    }
+
+    let compiled: Vec<String> = compiled_providers().iter().map(|k| k.to_string()).collect();
+    if compiled.is_empty() {
+        anyhow::bail!("no seed provider detected (none is compiled in; enable a provider feature)");
+    }
+    anyhow::bail!("no seed provider detected (compiled in: {})", compiled.join(", "))
+}
+
+/// Attach the detected provider kind to the enclosing tracing span.
+#[cfg(feature = "otel")]
+fn record_kind(kind: ProviderKind) {
+    tracing::Span::current().record("provider", tracing::field::display(kind));
+}
+
+static DETECTED: OnceLock<Box<dyn SeedProvider>> = OnceLock::new();
+
+/// Detect the seed provider once and return a shared handle to it.
+///
+/// Later calls return the cached provider without probing devices again.
+/// A failed detection is not cached, so callers may retry.
+pub fn detected_provider() -> Result<&'static dyn SeedProvider> {
+    if let Some(provider) = DETECTED.get() {
+        return Ok(provider.as_ref());
+    }
+
+    let provider = detect_provider()?;
+    Ok(DETECTED.get_or_init(|| provider).as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compiled_providers_reflect_the_enabled_features() {
+        let expected: Vec<ProviderKind> = [
+            (ProviderKind::Tpm, cfg!(feature = "tpm-provider")),
+            (ProviderKind::Keyring, cfg!(feature = "keyring")),
+            (ProviderKind::Hardware, cfg!(feature = "hardware")),
+        ]
+        .into_iter()
+        .filter_map(|(kind, enabled)| enabled.then_some(kind))
+        .collect();
+        assert_eq!(compiled_providers(), expected);
+    }
 }
@@ -0,0 +1,114 @@
+use std::fmt;
+use std::path::Path;
+
+/// Guest device nodes exposed by the TDX and SEV-SNP guest drivers.
+const TDX_GUEST_DEVICES: [&str; 2] = ["/dev/tdx_guest", "/dev/tdx-guest"];
+const SNP_GUEST_DEVICES: [&str; 1] = ["/dev/sev-guest"];
+/// TPM character devices. A TCTI pointing elsewhere (e.g. a swtpm socket)
+/// deliberately does not count: only a device node suggests real hardware.
+const TPM_DEVICES: [&str; 2] = ["/dev/tpmrm0", "/dev/tpm0"];
+
+/// The kind of environment we are running in, as far as we can tell.
+///
+/// This classifies the machine, independently of which seed provider is
+/// picked: a TDX guest may still use the TPM provider, for instance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CvmType {
+    /// Intel TDX guest.
+    Tdx,
+    /// AMD SEV-SNP guest.
+    Snp,
+    /// No confidential VM, but a TPM device (physical or vTPM).
+    TpmOnly,
+    /// A hypervisor but no TEE and no TPM.
+    Vm,
+    /// Neither a hypervisor, a TEE nor a TPM.
+    BareMetal,
+}
+
+impl CvmType {
+    /// Whether the environment offers a hardware root of trust at all: a
+    /// confidential VM or a TPM device.
+    pub fn has_hardware_root(self) -> bool {
+        matches!(self, CvmType::Tdx | CvmType::Snp | CvmType::TpmOnly)
+    }
+}
+
+impl fmt::Display for CvmType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CvmType::Tdx => "tdx",
+            CvmType::Snp => "snp",
+            CvmType::TpmOnly => "tpm-only",
+            CvmType::Vm => "vm",
+            CvmType::BareMetal => "bare-metal",
+        })
+    }
+}
+
+/// Classify the environment by probing the TEE guest device nodes, the
+/// TDX CPUID leaf and the CPUID hypervisor bit.
+///
+/// These are hints, not attestation: anything able to fake device nodes or
+/// CPUID can fool this, so use it for logging and coarse gating only.
+pub fn detect_cvm_type() -> CvmType {
+    if cpuid::is_tdx_guest() || any_exists(&TDX_GUEST_DEVICES) {
+        CvmType::Tdx
+    } else if any_exists(&SNP_GUEST_DEVICES) {
+        CvmType::Snp
+    } else if any_exists(&TPM_DEVICES) {
+        CvmType::TpmOnly
+    } else if cpuid::hypervisor_present() {
+        CvmType::Vm
+    } else {
+        CvmType::BareMetal
+    }
+}
+
+fn any_exists(paths: &[&str]) -> bool {
+    paths.iter().any(|path| Path::new(path).exists())
+}
+
+#[cfg(target_arch = "x86_64")]
+mod cpuid {
+    use std::arch::x86_64::{__cpuid, CpuidResult};
+
+    /// CPUID leaf a TDX module exposes to its guests.
+    const TDX_LEAF: u32 = 0x21;
+    /// Its vendor signature across EBX, EDX, ECX.
+    const TDX_SIGNATURE: &[u8; 12] = b"IntelTDX    ";
+
+    fn cpuid(leaf: u32) -> CpuidResult {
+        // SAFETY: CPUID is available on every x86_64 CPU, and a leaf above
+        // the maximum just returns data for the highest basic leaf.
+        unsafe { __cpuid(leaf) }
+    }
+
+    /// CPUID.1:ECX bit 31, set by every mainstream hypervisor.
+    pub fn hypervisor_present() -> bool {
+        cpuid(1).ecx & (1 << 31) != 0
+    }
+
+    pub fn is_tdx_guest() -> bool {
+        if cpuid(0).eax < TDX_LEAF {
+            return false;
+        }
+        let leaf = cpuid(TDX_LEAF);
+        let mut signature = [0u8; 12];
+        signature[..4].copy_from_slice(&leaf.ebx.to_le_bytes());
+        signature[4..8].copy_from_slice(&leaf.edx.to_le_bytes());
+        signature[8..].copy_from_slice(&leaf.ecx.to_le_bytes());
+        &signature == TDX_SIGNATURE
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod cpuid {
+    pub fn hypervisor_present() -> bool {
+        false
+    }
+
+    pub fn is_tdx_guest() -> bool {
+        false
+    }
+}
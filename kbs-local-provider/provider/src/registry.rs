@@ -0,0 +1,60 @@
+use anyhow::Result;
+use std::sync::{Mutex, PoisonError};
+
+use crate::{ProviderCheck, ProviderKind, SeedProvider};
+
+/// Reports whether a registered provider's platform is present.
+pub type DetectFn = fn() -> bool;
+/// Builds a registered provider once its platform was detected.
+pub type BuildFn = fn() -> Result<Box<dyn SeedProvider>>;
+
+#[derive(Clone, Copy)]
+struct Registration {
+    name: &'static str,
+    detect: DetectFn,
+    build: BuildFn,
+}
+
+static REGISTRY: Mutex<Vec<Registration>> = Mutex::new(Vec::new());
+
+/// Register an out-of-tree seed provider with `detect_provider()`.
+///
+/// Registered providers are tried in registration order, before the
+/// built-in ones, and are reported as `ProviderKind::Custom(name)`.
+/// Register before the first `detected_provider()` call, since that result
+/// is cached.
+pub fn register_provider(name: &'static str, detect: DetectFn, build: BuildFn) {
+    lock().push(Registration {
+        name,
+        detect,
+        build,
+    });
+}
+
+/// Build the first registered provider whose platform is detected.
+pub(crate) fn detect() -> Option<Result<(ProviderKind, Box<dyn SeedProvider>)>> {
+    let registration = snapshot().into_iter().find(|r| (r.detect)())?;
+    Some((registration.build)().map(|p| (ProviderKind::Custom(registration.name), p)))
+}
+
+/// Detection checks for every registered provider, for diagnostics.
+pub(crate) fn checks() -> Vec<ProviderCheck> {
+    snapshot()
+        .iter()
+        .map(|r| ProviderCheck {
+            kind: ProviderKind::Custom(r.name),
+            compiled: true,
+            detail: if (r.detect)() { "detected" } else { "not detected" }.to_string(),
+        })
+        .collect()
+}
+
+/// Copy the registrations out, so the callbacks run without the lock held
+/// and may themselves register providers or run detection.
+fn snapshot() -> Vec<Registration> {
+    lock().clone()
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Vec<Registration>> {
+    REGISTRY.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,135 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use zeroize::Zeroizing;
+
+/// Secret bytes, zeroized on drop and redacted in `Debug` output.
+///
+/// IKM, derived key material and serve payloads are held in this (or
+/// [`SecretString`]) rather than a plain `Vec<u8>`, so zeroization is
+/// enforced by the type instead of being left to each call site.
+///
+/// Size the buffer up front (`with_capacity`, `zeroed`) when it is going to
+/// grow: a reallocation leaves the old allocation behind unzeroized.
+///
+/// Dropping a non-empty buffer logs `secret zeroized` at trace level on
+/// [`AUDIT_TARGET`](crate::AUDIT_TARGET) (with the length, never the
+/// contents), as evidence for auditing how long derived seeds and other
+/// secrets live.
+#[derive(Clone, Default)]
+pub struct SecretBytes(Zeroizing<Vec<u8>>);
+
+impl SecretBytes {
+    /// Take ownership of `bytes`; the allocation is zeroized on drop.
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(Zeroizing::new(bytes))
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::new(Vec::with_capacity(capacity))
+    }
+
+    /// `len` zero bytes, to be filled in place.
+    pub fn zeroed(len: usize) -> Self {
+        Self::new(vec![0u8; len])
+    }
+}
+
+impl From<Vec<u8>> for SecretBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl From<Zeroizing<Vec<u8>>> for SecretBytes {
+    fn from(bytes: Zeroizing<Vec<u8>>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl Deref for SecretBytes {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.0
+    }
+}
+
+impl DerefMut for SecretBytes {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.0
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        // Runs before the `Zeroizing` field clears the allocation.
+        log_zeroized("SecretBytes", self.0.len());
+    }
+}
+
+impl fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretBytes([REDACTED; {}])", self.0.len())
+    }
+}
+
+/// A secret string, zeroized on drop and redacted in `Debug` output.
+///
+/// The same growth caveat and drop audit log as [`SecretBytes`] apply.
+#[derive(Clone, Default)]
+pub struct SecretString(Zeroizing<String>);
+
+impl SecretString {
+    /// Take ownership of `string`; the allocation is zeroized on drop.
+    pub fn new(string: String) -> Self {
+        Self(Zeroizing::new(string))
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::new(String::with_capacity(capacity))
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(string: String) -> Self {
+        Self::new(string)
+    }
+}
+
+impl From<Zeroizing<String>> for SecretString {
+    fn from(string: Zeroizing<String>) -> Self {
+        Self(string)
+    }
+}
+
+impl Deref for SecretString {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        &self.0
+    }
+}
+
+impl DerefMut for SecretString {
+    fn deref_mut(&mut self) -> &mut String {
+        &mut self.0
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        log_zeroized("SecretString", self.0.len());
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretString([REDACTED; {}])", self.0.len())
+    }
+}
+
+fn log_zeroized(kind: &str, len: usize) {
+    if len > 0 {
+        log::trace!(target: crate::AUDIT_TARGET, "secret zeroized ({kind}, {len} bytes)");
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::{Context, Result};
+use tss_esapi::abstraction::AsymmetricAlgorithmSelection;
+use tss_esapi::abstraction::ek::retrieve_ek_pubcert;
+use tss_esapi::interface_types::key_bits::RsaKeyBits;
+use tss_esapi::Context as TpmContext;
+
+#[cfg(feature = "verify-ek-chain")]
+pub use chain::{EK_ROOTS_ENV, load_roots, roots_from_env, verify_ek_cert};
+/// An X.509 certificate, as accepted by [`verify_ek_cert`].
+#[cfg(feature = "verify-ek-chain")]
+pub use picky::x509::Cert as Certificate;
+
+/// Read the manufacturer-provisioned RSA 2048 EK certificate (DER) from its
+/// TCG NV index (0x01C00002), matching the EK template in
+/// [`templates`](super::templates).
+pub fn read_ek_cert(ctx: &mut TpmContext) -> Result<Vec<u8>> {
+    retrieve_ek_pubcert(ctx, AsymmetricAlgorithmSelection::Rsa(RsaKeyBits::Rsa2048))
+        .context("failed to read the EK certificate from NV (none provisioned?)")
+}
+
+#[cfg(feature = "verify-ek-chain")]
+mod chain {
+    use anyhow::{Context, Result, bail};
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as B64;
+    use picky::x509::Cert;
+    use picky::x509::date::UtcDate;
+    use std::path::Path;
+
+    /// PEM bundle of TPM vendor roots (and any intermediates) to verify the
+    /// EK certificate against.
+    pub const EK_ROOTS_ENV: &str = "TPM_EK_ROOTS";
+
+    /// Longest chain [`verify_ek_cert`] builds, guarding against loops in
+    /// a bundle with cross-signed certificates.
+    const MAX_CHAIN_LENGTH: usize = 8;
+
+    /// Verify that the EK certificate `der` chains to one of `roots`.
+    ///
+    /// `roots` is the trust bundle: the vendor root CAs plus whatever
+    /// intermediates the vendor issues EK certificates from (these are
+    /// usually only published via the certificate's AIA URL, not stored in
+    /// the TPM). The chain is built by matching issuer to subject and must
+    /// end at a self-signed certificate from the bundle; every signature
+    /// and validity period on it is checked against the current time.
+    pub fn verify_ek_cert(der: &[u8], roots: &[Cert]) -> Result<()> {
+        let leaf = Cert::from_der(der).context("failed to parse the EK certificate")?;
+        let mut chain: Vec<&Cert> = Vec::new();
+        let mut current = &leaf;
+        while chain.last().is_none_or(|last| last.subject_name() != last.issuer_name()) {
+            if chain.len() == MAX_CHAIN_LENGTH {
+                bail!("EK certificate chain is longer than {MAX_CHAIN_LENGTH} certificates");
+            }
+            let issuer = current.issuer_name();
+            let Some(next) = roots.iter().find(|cert| cert.subject_name() == issuer) else {
+                bail!("no certificate in the root bundle issued the EK chain (issuer {issuer})");
+            };
+            chain.push(next);
+            current = next;
+        }
+
+        let now = UtcDate::now();
+        leaf.verifier()
+            .chain(chain.iter().copied())
+            .exact_date(&now)
+            .verify()
+            .context("EK certificate does not verify against the root bundle")
+    }
+
+    /// Load every certificate from a PEM bundle.
+    pub fn load_roots(path: &Path) -> Result<Vec<Cert>> {
+        let pem = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read EK root bundle {}", path.display()))?;
+        let mut roots = Vec::new();
+        let mut rest = pem.as_str();
+        while let Some(start) = rest.find("-----BEGIN CERTIFICATE-----") {
+            let body = &rest[start + "-----BEGIN CERTIFICATE-----".len()..];
+            let end = body.find("-----END CERTIFICATE-----").with_context(|| {
+                format!("unterminated certificate in EK root bundle {}", path.display())
+            })?;
+            let base64: String = body[..end].split_whitespace().collect();
+            let der = B64
+                .decode(base64)
+                .with_context(|| format!("invalid base64 in EK root bundle {}", path.display()))?;
+            roots.push(Cert::from_der(&der).with_context(|| {
+                format!("invalid certificate #{} in {}", roots.len() + 1, path.display())
+            })?);
+            rest = &body[end..];
+        }
+        if roots.is_empty() {
+            bail!("EK root bundle {} contains no certificates", path.display());
+        }
+        Ok(roots)
+    }
+
+    /// The root bundle named by `TPM_EK_ROOTS`, or `None` if unset.
+    ///
+    /// No vendor roots are built in: operators supply the bundle for the
+    /// TPM vendors they accept.
+    pub fn roots_from_env() -> Result<Option<Vec<Cert>>> {
+        match std::env::var(EK_ROOTS_ENV) {
+            Ok(path) => load_roots(Path::new(&path)).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::{Context, Result, anyhow, bail};
+use std::cell::RefMut;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tss_esapi::Context as TpmContext;
+use tss_esapi::attributes::ObjectAttributesBuilder;
+use tss_esapi::interface_types::algorithm::{HashingAlgorithm, PublicAlgorithm};
+use tss_esapi::interface_types::ecc::EccCurve;
+use tss_esapi::structures::{
+    EccPoint, EccScheme, HashScheme, KeyDerivationFunctionScheme, Public, PublicBuilder,
+    PublicEccParametersBuilder, PublicKeyRsa,
+};
+
+use super::{AkReader, templates};
+
+/// Canned [`AkReader`] outcomes. Public areas go through the same template
+/// check, double read and SPKI encoding as a TPM's; failures carry the same
+/// messages as the real TPM read.
+pub enum FakeAkReader {
+    /// Return this public area on every read.
+    Public(Public),
+    /// Return these public areas in turn, then the last one again; made by
+    /// [`FakeAkReader::sequence`].
+    Sequence(Vec<Public>, AtomicUsize),
+    /// No object at the AK handle.
+    NotFound,
+    /// An ECC key at the AK handle, which has no SPKI encoding here.
+    NotRsa,
+    /// A transient TPM failure, e.g. TPM_RC_RETRY.
+    Transient,
+}
+
+impl FakeAkReader {
+    /// Return `publics` in turn, e.g. two different ones to make a double
+    /// read fail.
+    pub fn sequence(publics: Vec<Public>) -> Self {
+        FakeAkReader::Sequence(publics, AtomicUsize::new(0))
+    }
+}
+
+impl AkReader for FakeAkReader {
+    fn read_ak_public(&self, _ak_handle: u32) -> Result<Public> {
+        match self {
+            FakeAkReader::Public(public) => Ok(public.clone()),
+            FakeAkReader::Sequence(publics, next) => {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                publics
+                    .get(index)
+                    .or(publics.last())
+                    .cloned()
+                    .context("fake AK reader has no public areas")
+            }
+            FakeAkReader::NotFound => {
+                Err(anyhow!("AK not found at handle — was attestation-agent-init run?"))
+            }
+            FakeAkReader::NotRsa => ecc_public(),
+            FakeAkReader::Transient => {
+                Err(anyhow!("TPM_RC_RETRY (fake)").context("failed to read AK public key"))
+            }
+        }
+    }
+
+    fn handle_resident(&self, _ak_handle: u32) -> Result<bool> {
+        Ok(!matches!(self, FakeAkReader::NotFound))
+    }
+
+    fn tpm(&self) -> Result<RefMut<'_, TpmContext>> {
+        bail!(
+            "a fake AK reader has no TPM for the HMAC, NV counter, firmware version or \
+             manufacturer"
+        )
+    }
+}
+
+/// An AK public area as [`templates::ak_rsa_template`] would create, with
+/// `modulus` as the RSA key.
+pub fn rsa_ak_public(modulus: &[u8]) -> Result<Public> {
+    let Public::Rsa { object_attributes, name_hashing_algorithm, auth_policy, parameters, .. } =
+        templates::ak_rsa_template()?
+    else {
+        unreachable!("the AK template is RSA");
+    };
+    Ok(Public::Rsa {
+        object_attributes,
+        name_hashing_algorithm,
+        auth_policy,
+        parameters,
+        unique: PublicKeyRsa::try_from(modulus.to_vec()).context("invalid RSA modulus")?,
+    })
+}
+
+/// An ECDSA P-256 signing key public area with an all-zero point.
+fn ecc_public() -> Result<Public> {
+    let object_attributes = ObjectAttributesBuilder::new()
+        .with_restricted(true)
+        .with_sign_encrypt(true)
+        .with_fixed_tpm(true)
+        .with_fixed_parent(true)
+        .with_sensitive_data_origin(true)
+        .with_user_with_auth(true)
+        .build()?;
+
+    let ecc_params = PublicEccParametersBuilder::new()
+        .with_ecc_scheme(EccScheme::EcDsa(HashScheme::new(HashingAlgorithm::Sha256)))
+        .with_curve(EccCurve::NistP256)
+        .with_key_derivation_function_scheme(KeyDerivationFunctionScheme::Null)
+        .with_restricted(true)
+        .with_is_signing_key(true)
+        .with_is_decryption_key(false)
+        .build()?;
+
+    PublicBuilder::new()
+        .with_public_algorithm(PublicAlgorithm::Ecc)
+        .with_name_hashing_algorithm(HashingAlgorithm::Sha256)
+        .with_object_attributes(object_attributes)
+        .with_ecc_parameters(ecc_params)
+        .with_ecc_unique_identifier(EccPoint::default())
+        .build()
+        .context("failed to build the fake ECC public area")
+}
@@ -0,0 +1,54 @@
+use anyhow::{Context, Result, bail};
+use tss_esapi::handles::TpmHandle;
+use tss_esapi::interface_types::algorithm::HashingAlgorithm;
+use tss_esapi::structures::MaxBuffer;
+use tss_esapi::Context as TpmContext;
+
+use super::{lockout, OWNER_PERSISTENT_RANGE, PLATFORM_PERSISTENT_RANGE};
+use crate::SecretBytes;
+
+const HMAC_KEY_HANDLE_ENV: &str = "TPM_HMAC_KEY_HANDLE";
+
+/// The persistent HMAC key handle from `TPM_HMAC_KEY_HANDLE` (hex), if set.
+///
+/// Unlike the AK there is no default: the key only exists if an operator
+/// provisioned one, e.g. a SHA-256 keyed-hash key under the owner SRK:
+///
+///   tpm2_createprimary -C o -c srk.ctx
+///   tpm2_create -C srk.ctx -G hmac -c hmac.ctx
+///   tpm2_evictcontrol -C o -c hmac.ctx 0x81010003
+///
+/// The key must have an empty authValue (the default) and the `sign`
+/// attribute. Its sensitive part never leaves the TPM, which is the point.
+pub fn hmac_key_handle_from_env() -> Result<Option<u32>> {
+    let Ok(value) = std::env::var(HMAC_KEY_HANDLE_ENV) else {
+        return Ok(None);
+    };
+    let digits = value.trim_start_matches("0x").trim_start_matches("0X");
+    let handle = u32::from_str_radix(digits, 16)
+        .with_context(|| format!("{HMAC_KEY_HANDLE_ENV} {value:?} is not a hex handle"))?;
+    if !OWNER_PERSISTENT_RANGE.contains(&handle) && !PLATFORM_PERSISTENT_RANGE.contains(&handle) {
+        bail!("{HMAC_KEY_HANDLE_ENV} {handle:#X} is not a persistent handle");
+    }
+    Ok(Some(handle))
+}
+
+/// HMAC-SHA256 of `message` computed by the TPM with the key at `handle`.
+///
+/// `message` is at most 1024 bytes (TPM2B_MAX_BUFFER); an init_data digest
+/// always fits.
+pub fn hmac(ctx: &mut TpmContext, handle: u32, message: &[u8]) -> Result<SecretBytes> {
+    let buffer = MaxBuffer::try_from(message.to_vec())
+        .with_context(|| format!("{} bytes is too long for TPM2_HMAC", message.len()))?;
+    let tpm_handle: TpmHandle = handle.try_into().context("invalid HMAC key handle")?;
+    let key = ctx
+        .execute_with_nullauth_session(|ctx| ctx.tr_from_tpm_public(tpm_handle))
+        .map_err(lockout::explain)
+        .with_context(|| format!("HMAC key not found at handle {handle:#X}"))?;
+
+    let digest = ctx
+        .execute_with_nullauth_session(|ctx| ctx.hmac(key, buffer, HashingAlgorithm::Sha256))
+        .map_err(lockout::explain)
+        .context("TPM2_HMAC failed")?;
+    Ok(SecretBytes::new(digest.value().to_vec()))
+}
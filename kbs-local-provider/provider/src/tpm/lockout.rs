@@ -0,0 +1,64 @@
+use anyhow::{Context, Result};
+use std::fmt;
+use tss_esapi::constants::{PropertyTag, Tss2ResponseCodeKind};
+use tss_esapi::Context as TpmContext;
+
+const LOCKOUT_MESSAGE: &str = "TPM is in dictionary-attack lockout — wait for the lockout \
+                               recovery interval or reset the DA counter \
+                               (tpm2_dictionarylockout --clear-lockout)";
+
+/// Whether a TPM error is TPM_RC_LOCKOUT.
+pub fn is_lockout(err: &tss_esapi::Error) -> bool {
+    matches!(
+        err,
+        tss_esapi::Error::Tss2Error(rc) if rc.kind() == Some(Tss2ResponseCodeKind::Lockout)
+    )
+}
+
+/// Convert a TPM error, replacing an opaque TPM_RC_LOCKOUT with an
+/// actionable message. Meant for `map_err` on auth-requiring TPM calls.
+pub fn explain(err: tss_esapi::Error) -> anyhow::Error {
+    if is_lockout(&err) {
+        anyhow::Error::new(err).context(LOCKOUT_MESSAGE)
+    } else {
+        err.into()
+    }
+}
+
+/// The TPM's dictionary-attack protection state.
+pub struct LockoutParameters {
+    /// Authorization failures counted so far (TPM2_PT_LOCKOUT_COUNTER).
+    pub counter: u32,
+    /// Failures before the TPM enters lockout (TPM2_PT_MAX_AUTH_FAIL).
+    pub max_auth_fail: u32,
+    /// Seconds before one failure is forgiven (TPM2_PT_LOCKOUT_INTERVAL).
+    pub interval: u32,
+    /// Seconds before lockoutAuth may be retried after a failure (TPM2_PT_LOCKOUT_RECOVERY).
+    pub recovery: u32,
+}
+
+impl fmt::Display for LockoutParameters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "lockout counter {}/{}, interval {}s, recovery {}s",
+            self.counter, self.max_auth_fail, self.interval, self.recovery
+        )
+    }
+}
+
+/// Read the dictionary-attack parameters from the TPM.
+pub fn lockout_parameters(ctx: &mut TpmContext) -> Result<LockoutParameters> {
+    let mut property = |tag: PropertyTag| -> Result<u32> {
+        ctx.get_tpm_property(tag)
+            .with_context(|| format!("failed to read TPM property {tag:?}"))?
+            .with_context(|| format!("TPM does not report {tag:?}"))
+    };
+
+    Ok(LockoutParameters {
+        counter: property(PropertyTag::LockoutCounter)?,
+        max_auth_fail: property(PropertyTag::MaxAuthFail)?,
+        interval: property(PropertyTag::LockoutInterval)?,
+        recovery: property(PropertyTag::LockoutRecovery)?,
+    })
+}
@@ -1,19 +1,227 @@
 use anyhow::{bail, Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as B64;
+use std::cell::{RefCell, RefMut};
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use tss_esapi::abstraction::public::DecodedKey;
-use tss_esapi::handles::TpmHandle;
+use tss_esapi::constants::{CapabilityType, PropertyTag};
+use tss_esapi::handles::{ObjectHandle, TpmHandle};
+use tss_esapi::interface_types::algorithm::HashingAlgorithm;
+use tss_esapi::structures::{CapabilityData, PcrSelectionList, PcrSlot, Public};
 use tss_esapi::tcti_ldr::TctiNameConf;
 use tss_esapi::Context as TpmContext;
-use zeroize::Zeroizing;
 
-use crate::SeedProvider;
+use crate::{ProviderInfo, ProviderKind, SecretBytes, SeedProvider};
+
+pub mod ek_cert;
+#[cfg(feature = "test-fakes")]
+pub mod fakes;
+pub mod hmac;
+pub mod lockout;
+pub mod nv_counter;
+pub mod provision;
+mod public_cache;
+pub mod sign;
+pub mod templates;
+
+pub use ek_cert::read_ek_cert;
+#[cfg(feature = "verify-ek-chain")]
+pub use ek_cert::{Certificate, verify_ek_cert};
+pub use hmac::hmac_key_handle_from_env;
+pub use sign::{prove_possession, sign_with_ak};
+
+use templates::AK_HANDLE;
 
-const AK_HANDLE: u32 = 0x81010002;
 const DEFAULT_TPM_DEVICE: &str = "/dev/tpm0";
+const TPM_DEVICE_ENV: &str = "TPM_DEVICE";
+const AK_HANDLE_ENV: &str = "TPM_AK_HANDLE";
+const NV_COUNTER_ENV: &str = "TPM_NV_COUNTER_INDEX";
+/// Length of the TPM HMAC-SHA256 mixed into the IKM.
+const HMAC_LEN: usize = 32;
+
+/// Persistent handles allocated to the owner (storage) hierarchy.
+pub const OWNER_PERSISTENT_RANGE: std::ops::RangeInclusive<u32> = 0x81000000..=0x817FFFFF;
+/// Persistent handles allocated to the platform hierarchy.
+pub const PLATFORM_PERSISTENT_RANGE: std::ops::RangeInclusive<u32> = 0x81800000..=0x81FFFFFF;
+/// Handles requested per TPM2_GetCapability round trip.
+const HANDLES_PER_QUERY: u32 = 64;
+/// Variables `TctiNameConf::from_environment_variable` consults, in order.
+const TCTI_ENVS: [&str; 3] = ["TPM2TOOLS_TCTI", "TCTI", "TEST_TCTI"];
+/// Where the dynamic loader usually finds the TSS TCTI libraries, besides
+/// `LD_LIBRARY_PATH`.
+const LIBRARY_DIRS: [&str; 9] = [
+    "/lib",
+    "/lib64",
+    "/usr/lib",
+    "/usr/lib64",
+    "/usr/local/lib",
+    "/lib/x86_64-linux-gnu",
+    "/usr/lib/x86_64-linux-gnu",
+    "/lib/aarch64-linux-gnu",
+    "/usr/lib/aarch64-linux-gnu",
+];
+
+/// Resolve the TCTI the same way the tpm2 tools do.
+///
+/// An explicit `TPM_DEVICE` path takes precedence; otherwise the standard
+/// `TPM2TOOLS_TCTI`/`TCTI`/`TEST_TCTI` variables are honored (e.g. for
+/// abrmd or swtpm), falling back to `device:/dev/tpm0`.
+pub fn tcti_from_env() -> Result<TctiNameConf> {
+    if let Ok(device) = std::env::var(TPM_DEVICE_ENV) {
+        return device_tcti(&device);
+    }
+    if TCTI_ENVS.iter().any(|var| std::env::var_os(var).is_some()) {
+        return TctiNameConf::from_environment_variable()
+            .context("failed to parse TCTI from environment");
+    }
+    device_tcti(DEFAULT_TPM_DEVICE)
+}
+
+/// Create a TPM context on `tcti`, telling a missing TSS TCTI library apart
+/// from a missing TPM.
+///
+/// tss-esapi loads the TCTI (`libtss2-tcti-device.so.0` and friends) at
+/// runtime, so on an image without it context creation fails with the same
+/// opaque loader error as when there is no TPM at all. On failure this
+/// checks which one it is: the device node for a `device:` TCTI, then the
+/// library. The library check only looks in `LD_LIBRARY_PATH` and the usual
+/// directories, so it can miss a library the loader would find elsewhere.
+pub fn create_context(tcti: TctiNameConf) -> Result<TpmContext> {
+    let name = CString::try_from(tcti.clone())
+        .ok()
+        .and_then(|name| name.into_string().ok())
+        .unwrap_or_default();
+    TpmContext::new(tcti).map_err(|err| explain_context_error(err, &name))
+}
+
+fn explain_context_error(err: tss_esapi::Error, tcti: &str) -> anyhow::Error {
+    let err = anyhow::Error::new(err).context("failed to create TPM context");
+    let (kind, conf) = tcti.split_once(':').unwrap_or((tcti, ""));
+    if kind.is_empty() {
+        return err;
+    }
+    if kind == "device" {
+        let device = if conf.is_empty() { DEFAULT_TPM_DEVICE } else { conf };
+        if !Path::new(device).exists() {
+            return err.context(format!("no TPM present: {device} does not exist"));
+        }
+    }
+    let library = format!("libtss2-tcti-{kind}.so.0");
+    if !library_installed(&library) {
+        return err.context(format!(
+            "the TSS TCTI library {library} is not installed (a packaging problem, not a \
+             missing TPM): add it to the image, e.g. the libtss2-tcti-{kind}0 or tpm2-tss \
+             package"
+        ));
+    }
+    err
+}
+
+fn library_installed(library: &str) -> bool {
+    let ld_library_path = std::env::var("LD_LIBRARY_PATH").unwrap_or_default();
+    ld_library_path
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+        .chain(LIBRARY_DIRS)
+        .any(|dir| Path::new(dir).join(library).exists())
+}
 
-/// Check if a TPM device is available.
+fn device_tcti(device: &str) -> Result<TctiNameConf> {
+    TctiNameConf::from_str(&format!("device:{device}")).context("failed to create TCTI config")
+}
+
+/// The AK's persistent handle: `TPM_AK_HANDLE` (hex) if set, else 0x81010002.
+///
+/// Both the provisioning tool and the reader resolve the handle through this,
+/// so they agree as long as they run with the same environment.
+pub fn ak_handle_from_env() -> Result<u32> {
+    let Ok(value) = std::env::var(AK_HANDLE_ENV) else {
+        return Ok(AK_HANDLE);
+    };
+    let digits = value.trim_start_matches("0x").trim_start_matches("0X");
+    let handle = u32::from_str_radix(digits, 16)
+        .with_context(|| format!("{AK_HANDLE_ENV} {value:?} is not a hex handle"))?;
+    if !OWNER_PERSISTENT_RANGE.contains(&handle) && !PLATFORM_PERSISTENT_RANGE.contains(&handle) {
+        bail!("{AK_HANDLE_ENV} {handle:#X} is not a persistent handle");
+    }
+    Ok(handle)
+}
+
+/// The NV counter index from `TPM_NV_COUNTER_INDEX` (hex), if set.
+///
+/// Like the AK handle, the provider and the counter-increment subcommand
+/// resolve it through this so they agree on the index.
+pub fn nv_counter_from_env() -> Result<Option<u32>> {
+    let Ok(value) = std::env::var(NV_COUNTER_ENV) else {
+        return Ok(None);
+    };
+    let digits = value.trim_start_matches("0x").trim_start_matches("0X");
+    let index = u32::from_str_radix(digits, 16)
+        .with_context(|| format!("{NV_COUNTER_ENV} {value:?} is not a hex NV index"))?;
+    Ok(Some(index))
+}
+
+/// Check if a TPM is available: the configured device exists, or a TCTI is
+/// configured in the environment (which may not be backed by a device node).
 pub fn detect_platform() -> bool {
-    std::path::Path::new(DEFAULT_TPM_DEVICE).exists()
+    match std::env::var(TPM_DEVICE_ENV) {
+        Ok(device) => std::path::Path::new(&device).exists(),
+        Err(_) => {
+            TCTI_ENVS.iter().any(|var| std::env::var_os(var).is_some())
+                || std::path::Path::new(DEFAULT_TPM_DEVICE).exists()
+        }
+    }
+}
+
+/// Describe what `detect_platform` sees, for diagnostics.
+pub fn detection_status() -> String {
+    if let Ok(device) = std::env::var(TPM_DEVICE_ENV) {
+        let found = std::path::Path::new(&device).exists();
+        let found = if found { "present" } else { "not found" };
+        return format!("{device} ({TPM_DEVICE_ENV}): {found}");
+    }
+    if let Some(var) = TCTI_ENVS.iter().find(|var| std::env::var_os(var).is_some()) {
+        return format!("TCTI configured via {var}");
+    }
+    if std::path::Path::new(DEFAULT_TPM_DEVICE).exists() {
+        format!("{DEFAULT_TPM_DEVICE}: present")
+    } else {
+        format!("{DEFAULT_TPM_DEVICE}: not found")
+    }
+}
+
+/// List every persistent handle resident in the TPM, in ascending order.
+///
+/// Uses TPM2_GetCapability(TPM_CAP_HANDLES), which is read-only and needs no
+/// authorization, so it is safe to run for diagnostics.
+pub fn list_persistent_handles() -> Result<Vec<u32>> {
+    let mut ctx = create_context(tcti_from_env()?)?;
+
+    let mut handles = Vec::new();
+    let mut next = *OWNER_PERSISTENT_RANGE.start();
+    loop {
+        let (data, more) = ctx
+            .execute_without_session(|ctx| {
+                ctx.get_capability(CapabilityType::Handles, next, HANDLES_PER_QUERY)
+            })
+            .context("failed to query TPM persistent handles")?;
+        let CapabilityData::Handles(list) = data else {
+            bail!("TPM returned non-handle data for TPM_CAP_HANDLES");
+        };
+
+        let batch: Vec<u32> = list.into_inner().into_iter().map(u32::from).collect();
+        let Some(&last) = batch.last() else {
+            break;
+        };
+        handles.extend(batch);
+        if !more || last == *PLATFORM_PERSISTENT_RANGE.end() {
+            break;
+        }
+        next = last + 1;
+    }
+    Ok(handles)
 }
 
 /// TPM-based seed provider.
@@ -22,44 +230,475 @@ pub fn detect_platform() -> bool {
 /// DER-encoded SubjectPublicKeyInfo as input keying material. This is
 /// the same byte representation that the CoCo attestation-agent puts
 /// in the `ak_public` field of TPM evidence.
+#[derive(Default)]
 pub struct TpmSeedProvider {
-    device: String,
+    /// Explicit device path; when unset the TCTI comes from `tcti_from_env`.
+    device: Option<String>,
+    /// Read the AK public twice and require identical bytes.
+    double_read: bool,
+    /// NV counter mixed into the IKM; when unset, `TPM_NV_COUNTER_INDEX` is used.
+    nv_counter: Option<u32>,
+    /// Provision a missing AK instead of failing.
+    auto_provision: bool,
+    /// File caching the AK public area across boots.
+    public_cache: Option<PathBuf>,
+    /// Mix the TPM firmware version into the IKM.
+    firmware_version: bool,
+    /// Mix the TPM manufacturer ID and model into the IKM.
+    manufacturer: bool,
+    /// HMAC key handle and the message the TPM HMACs into the IKM.
+    hmac: Option<(u32, Vec<u8>)>,
+    /// Replaces the TPM as the source of the AK public key.
+    #[cfg(feature = "test-fakes")]
+    ak_reader: Option<Box<dyn AkReader + Send + Sync>>,
 }
 
-impl Default for TpmSeedProvider {
-    fn default() -> Self {
-        Self {
-            device: DEFAULT_TPM_DEVICE.to_string(),
-        }
+impl TpmSeedProvider {
+    /// Use this TPM device instead of the environment-resolved TCTI.
+    pub fn with_device(mut self, device: impl Into<String>) -> Self {
+        self.device = Some(device.into());
+        self
+    }
+
+    /// Hardened mode: read the AK public key twice and bail unless both
+    /// reads return the same DER, so a nondeterministic (flaky or malicious)
+    /// TPM cannot silently change the derived seed.
+    pub fn with_double_read(mut self, double_read: bool) -> Self {
+        self.double_read = double_read;
+        self
+    }
+
+    /// Append the value of the TPM NV counter at `index` (big-endian u64) to
+    /// the IKM, so incrementing the counter rotates every derived key.
+    ///
+    /// Incrementing is irreversible: the previous identity cannot be derived
+    /// again. See [`nv_counter::increment`].
+    pub fn with_nv_counter(mut self, index: u32) -> Self {
+        self.nv_counter = Some(index);
+        self
+    }
+
+    /// If no AK is resident at the handle, provision one (as
+    /// attestation-agent-init would, see [`provision::ProvisionOptions::from_env`])
+    /// and retry the read once. Off by default: normally the AK is created by
+    /// a separate, explicit init step.
+    pub fn with_auto_provision(mut self, auto_provision: bool) -> Self {
+        self.auto_provision = auto_provision;
+        self
+    }
+
+    /// Cache the AK public area at `path` after the first TPM read, and use
+    /// the cache on later boots instead of loading the AK.
+    ///
+    /// Each boot then costs one TPM2_GetCapability, which checks that the
+    /// AK handle is still occupied, instead of the ESYS load and
+    /// TPM2_ReadPublic. If the handle is empty the cache is removed; a
+    /// malformed or foreign cache is ignored and rewritten.
+    ///
+    /// An AK re-created at the same handle is not noticed at start-up.
+    /// [`SeedProvider::fresh_ikm`] (the `KBS_WATCH_AK` watcher) reads the
+    /// TPM and rewrites a cache that no longer matches it, so the restart it
+    /// triggers derives from the new AK.
+    pub fn with_public_cache(mut self, path: impl Into<PathBuf>) -> Self {
+        self.public_cache = Some(path.into());
+        self
+    }
+
+    /// Append the TPM firmware version (TPM_PT_FIRMWARE_VERSION_1/2, as a
+    /// big-endian u64) to the IKM, binding the identity to the firmware.
+    ///
+    /// Every TPM firmware update then rotates all derived keys, which only
+    /// some threat models want: an update can silently lock a workload out
+    /// of its previous identity.
+    pub fn with_firmware_version(mut self, firmware_version: bool) -> Self {
+        self.firmware_version = firmware_version;
+        self
+    }
+
+    /// Append the TPM manufacturer ID (TPM_PT_MANUFACTURER) and model
+    /// (TPM_PT_VENDOR_STRING_1..4), 20 bytes big-endian, to the IKM.
+    ///
+    /// The AK public key already differs per TPM; this adds an explicit,
+    /// auditable binding to the TPM part, and keeps keys distinct even
+    /// should two AK public keys ever collide. The properties are immutable,
+    /// so this never rotates keys by itself, but the derivation now depends
+    /// on them: reproducing it offline needs them as well as the AK.
+    pub fn with_manufacturer(mut self, manufacturer: bool) -> Self {
+        self.manufacturer = manufacturer;
+        self
+    }
+
+    /// Append HMAC-SHA256(key at `handle`, `message`), computed by the TPM,
+    /// to the IKM. With the init_data digest as `message`, deriving then
+    /// takes the TPM itself, with that key resident, rather than only the
+    /// AK public key, which is no secret.
+    ///
+    /// The key must be provisioned beforehand; see
+    /// [`hmac::hmac_key_handle_from_env`].
+    pub fn with_hmac_key(mut self, handle: u32, message: impl Into<Vec<u8>>) -> Self {
+        self.hmac = Some((handle, message.into()));
+        self
+    }
+
+    /// Take the AK from `reader` instead of the TPM, to test the error
+    /// handling and IKM assembly without TPM hardware or a simulator.
+    ///
+    /// The public area goes through the same public cache, template check,
+    /// double read and SPKI encoding as a TPM's. Auto-provisioning does not
+    /// apply, and the options that need a TPM (HMAC, NV counter, firmware
+    /// version, manufacturer) fail unless `reader` has one.
+    #[cfg(feature = "test-fakes")]
+    pub fn with_ak_reader(mut self, reader: Box<dyn AkReader + Send + Sync>) -> Self {
+        self.ak_reader = Some(reader);
+        self
     }
 }
 
 impl SeedProvider for TpmSeedProvider {
-    fn ikm(&self) -> Result<Zeroizing<Vec<u8>>> {
-        ak_public_key_der(&self.device)
+    #[cfg_attr(feature = "otel", tracing::instrument(skip_all, fields(provider = "tpm")))]
+    fn ikm(&self) -> Result<SecretBytes> {
+        self.read(true)
+    }
+
+    /// Always reads the AK from the TPM. A configured public cache is not
+    /// trusted, but rewritten if it differs from the TPM's AK.
+    fn fresh_ikm(&self) -> Result<SecretBytes> {
+        self.read(false)
+    }
+
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Tpm
+    }
+
+    fn info(&self) -> Result<ProviderInfo> {
+        // Only RSA AKs can be encoded as IKM (see `spki_der_from_public`).
+        Ok(ProviderInfo {
+            kind: self.kind().to_string(),
+            algorithm: Some("rsa".to_string()),
+            source_handle: Some(format!("{:#010x}", ak_handle_from_env()?)),
+        })
     }
 }
 
-/// Read the AK public key from TPM handle 0x81010002 and return it as
-/// DER-encoded SubjectPublicKeyInfo bytes.
-fn ak_public_key_der(tpm_device: &str) -> Result<Zeroizing<Vec<u8>>> {
-    let tcti = TctiNameConf::from_str(&format!("device:{tpm_device}"))
-        .context("failed to create TCTI config")?;
-    let mut ctx = TpmContext::new(tcti).context("failed to create TPM context")?;
+impl TpmSeedProvider {
+    /// Read the IKM from the TPM (or the AK reader), provisioning the AK if
+    /// enabled; `trust_cache` lets a valid public cache replace the AK read.
+    fn read(&self, trust_cache: bool) -> Result<SecretBytes> {
+        let nv_counter = match self.nv_counter {
+            Some(index) => Some(index),
+            None => nv_counter_from_env()?,
+        };
+        #[cfg(feature = "test-fakes")]
+        if let Some(reader) = &self.ak_reader {
+            return self.read_ikm(reader.as_ref(), trust_cache, nv_counter);
+        }
+        let tcti = match &self.device {
+            Some(device) => device_tcti(device)?,
+            None => tcti_from_env()?,
+        };
+        let read = |tcti| self.read_ikm(&TpmAkReader::open(tcti)?, trust_cache, nv_counter);
+        match read(tcti.clone()) {
+            Err(err) if self.auto_provision => match ak_resident(tcti.clone()) {
+                Ok(true) => Err(err),
+                Ok(false) => {
+                    log::warn!("no AK at the handle ({err:#}); auto-provisioning one");
+                    let options = provision::ProvisionOptions::from_env()?;
+                    provision::provision_ak(tcti.clone(), &options)?;
+                    read(tcti)
+                }
+                Err(e) => Err(anyhow::anyhow!(
+                    "{err:#}; checking whether the AK is resident also failed: {e:#}"
+                )),
+            },
+            result => result,
+        }
+    }
 
-    let tpm_handle: TpmHandle = AK_HANDLE
-        .try_into()
-        .context("invalid AK handle")?;
+    /// Read the AK public key from its persistent handle (0x81010002 unless
+    /// overridden) through `reader` and return it as DER-encoded
+    /// SubjectPublicKeyInfo bytes, followed by the enabled optional inputs:
+    ///
+    /// 1. with `hmac`, the TPM-computed HMAC;
+    /// 2. with `nv_counter`, the counter's value;
+    /// 3. with `firmware_version`, the TPM firmware version;
+    /// 4. with `manufacturer`, the manufacturer ID and model.
+    ///
+    /// The key is first compared with the AK template (see
+    /// [`templates::check_ak_public`]). With `double_read` it is read a
+    /// second time and both encodings must match. With `trust_cache` a
+    /// valid public cache replaces the TPM read (see
+    /// [`Self::with_public_cache`]).
+    fn read_ikm(
+        &self,
+        reader: &dyn AkReader,
+        trust_cache: bool,
+        nv_counter: Option<u32>,
+    ) -> Result<SecretBytes> {
+        let public_cache = self.public_cache.as_deref();
+        let ak_handle = ak_handle_from_env()?;
 
-    let ak_obj = ctx
-        .execute_with_nullauth_session(|ctx| ctx.tr_from_tpm_public(tpm_handle))
-        .context("AK not found at handle — was attestation-agent-init run?")?;
+        let cached = match public_cache {
+            Some(path) if !reader.handle_resident(ak_handle)? => {
+                public_cache::invalidate(path);
+                None
+            }
+            Some(path) if trust_cache => public_cache::load(path, ak_handle),
+            _ => None,
+        };
+        let public = match cached {
+            Some(public) => {
+                log::info!("using cached AK public area for handle {ak_handle:#X}");
+                public
+            }
+            None => {
+                let public = reader.read_ak_public(ak_handle)?;
+                let stale = |path| public_cache::load(path, ak_handle).as_ref() != Some(&public);
+                if let Some(path) = public_cache.filter(|path| stale(path)) {
+                    match public_cache::store(path, ak_handle, &public) {
+                        Ok(()) => log::info!("cached AK public area at {}", path.display()),
+                        Err(err) => log::warn!("could not cache AK public area: {err:#}"),
+                    }
+                }
+                public
+            }
+        };
+        templates::check_ak_public(&public, ak_handle, templates::AkTemplateCheck::from_env()?)?;
+        let der = spki_der_from_public(&public)?;
+        if self.double_read {
+            let second = spki_der_from_public(&reader.read_ak_public(ak_handle)?)?;
+            if der != second {
+                bail!(
+                    "AK public key changed between consecutive reads; refusing to derive from an \
+                     inconsistent TPM"
+                );
+            }
+        }
+
+        log::info!("read AK public key from handle {:#X} ({} bytes DER)", ak_handle, der.len());
+        // Sized up front: growing the buffer once the HMAC, a secret, is in it
+        // would leave a copy behind unzeroized (see `SecretBytes`).
+        let capacity = der.len()
+            + self.hmac.as_ref().map_or(0, |_| HMAC_LEN)
+            + nv_counter.map_or(0, |_| size_of::<u64>())
+            + if self.firmware_version { size_of::<u64>() } else { 0 }
+            + if self.manufacturer { size_of::<u32>() + 16 } else { 0 };
+        let mut ikm = SecretBytes::with_capacity(capacity);
+        ikm.extend_from_slice(&der);
+
+        if let Some((handle, message)) = &self.hmac {
+            let mac = hmac::hmac(&mut *reader.tpm()?, *handle, message)?;
+            if mac.len() != HMAC_LEN {
+                bail!("TPM returned a {}-byte HMAC-SHA256", mac.len());
+            }
+            log::info!("mixing the TPM HMAC from key {handle:#X} into the IKM");
+            ikm.extend_from_slice(&mac);
+        }
+        if let Some(index) = nv_counter {
+            let value = nv_counter::read(&mut *reader.tpm()?, index)?;
+            log::info!("mixing NV counter {index:#X} = {value} into the IKM");
+            ikm.extend_from_slice(&value.to_be_bytes());
+        }
+        if self.firmware_version {
+            let version = firmware_version(&mut *reader.tpm()?)?;
+            log::info!("mixing TPM firmware version {version:#018x} into the IKM");
+            ikm.extend_from_slice(&version.to_be_bytes());
+        }
+        if self.manufacturer {
+            let (manufacturer, model) = manufacturer_and_model(&mut *reader.tpm()?)?;
+            log::info!(
+                "mixing TPM manufacturer {:?} model {:?} into the IKM",
+                String::from_utf8_lossy(&manufacturer.to_be_bytes()),
+                String::from_utf8_lossy(&model).trim_end_matches(['\0', ' '])
+            );
+            ikm.extend_from_slice(&manufacturer.to_be_bytes());
+            ikm.extend_from_slice(&model);
+        }
+        debug_assert_eq!(ikm.len(), capacity);
+        Ok(ikm)
+    }
+}
+
+/// Where [`TpmSeedProvider`] reads the AK from: the TPM, or in tests a
+/// `fakes::FakeAkReader` (see [`TpmSeedProvider::with_ak_reader`]).
+pub trait AkReader {
+    /// The public area of the AK at `ak_handle` (TPM2_ReadPublic).
+    fn read_ak_public(&self, ak_handle: u32) -> Result<Public>;
+
+    /// Whether `ak_handle` is occupied; checks the public cache.
+    fn handle_resident(&self, ak_handle: u32) -> Result<bool>;
+
+    /// The TPM itself, for the IKM inputs other than the AK (HMAC, NV
+    /// counter, firmware version, manufacturer).
+    fn tpm(&self) -> Result<RefMut<'_, TpmContext>>;
+}
+
+/// The default [`AkReader`]: one TPM context for the whole IKM read.
+struct TpmAkReader(RefCell<TpmContext>);
+
+impl TpmAkReader {
+    fn open(tcti: TctiNameConf) -> Result<Self> {
+        Ok(Self(RefCell::new(create_context(tcti)?)))
+    }
+}
+
+impl AkReader for TpmAkReader {
+    fn read_ak_public(&self, ak_handle: u32) -> Result<Public> {
+        read_ak_public(&mut self.0.borrow_mut(), ak_handle)
+    }
+
+    fn handle_resident(&self, ak_handle: u32) -> Result<bool> {
+        handle_resident(&mut self.0.borrow_mut(), ak_handle)
+    }
+
+    fn tpm(&self) -> Result<RefMut<'_, TpmContext>> {
+        Ok(self.0.borrow_mut())
+    }
+}
+
+/// The TPM firmware version: TPM_PT_FIRMWARE_VERSION_1 in the high 32 bits,
+/// TPM_PT_FIRMWARE_VERSION_2 in the low ones.
+fn firmware_version(ctx: &mut TpmContext) -> Result<u64> {
+    let mut property = |tag: PropertyTag| -> Result<u32> {
+        ctx.get_tpm_property(tag)
+            .with_context(|| format!("failed to read TPM property {tag:?}"))?
+            .with_context(|| format!("TPM does not report {tag:?}"))
+    };
+    let high = property(PropertyTag::FirmwareVersion1)?;
+    let low = property(PropertyTag::FirmwareVersion2)?;
+    Ok((u64::from(high) << 32) | u64::from(low))
+}
+
+/// The TPM manufacturer ID, four ASCII characters such as `IFX`, and the
+/// model, the concatenated vendor strings (unreported ones read as zero).
+fn manufacturer_and_model(ctx: &mut TpmContext) -> Result<(u32, [u8; 16])> {
+    let manufacturer = ctx
+        .get_tpm_property(PropertyTag::Manufacturer)
+        .context("failed to read TPM property Manufacturer")?
+        .context("TPM does not report Manufacturer")?;
+    let mut model = [0u8; 16];
+    let vendor_strings = [
+        PropertyTag::VendorString1,
+        PropertyTag::VendorString2,
+        PropertyTag::VendorString3,
+        PropertyTag::VendorString4,
+    ];
+    for (chunk, tag) in model.chunks_exact_mut(4).zip(vendor_strings) {
+        let value = ctx
+            .get_tpm_property(tag)
+            .with_context(|| format!("failed to read TPM property {tag:?}"))?
+            .unwrap_or(0);
+        chunk.copy_from_slice(&value.to_be_bytes());
+    }
+    Ok((manufacturer, model))
+}
+
+fn read_ak_der(ctx: &mut TpmContext, ak_handle: u32) -> Result<Vec<u8>> {
+    spki_der_from_public(&read_ak_public(ctx, ak_handle)?)
+}
+
+fn read_ak_public(ctx: &mut TpmContext, ak_handle: u32) -> Result<Public> {
+    let ak_obj = ak_object(ctx, ak_handle)?;
 
     let (ak_public, _, _) = ctx
         .read_public(ak_obj.into())
+        .map_err(lockout::explain)
         .context("failed to read AK public key")?;
+    Ok(ak_public)
+}
+
+/// Whether `handle` is occupied, via one TPM2_GetCapability(TPM_CAP_HANDLES)
+/// rather than a full TPM2_ReadPublic.
+fn handle_resident(ctx: &mut TpmContext, handle: u32) -> Result<bool> {
+    let (data, _) = ctx
+        .execute_without_session(|ctx| ctx.get_capability(CapabilityType::Handles, handle, 1))
+        .with_context(|| format!("failed to query the TPM for handle {handle:#X}"))?;
+    let CapabilityData::Handles(list) = data else {
+        bail!("TPM returned non-handle data for TPM_CAP_HANDLES");
+    };
+    Ok(list.into_inner().into_iter().next().map(u32::from) == Some(handle))
+}
+
+fn ak_resident(tcti: TctiNameConf) -> Result<bool> {
+    let mut ctx = create_context(tcti)?;
+    provision::ak_present(&mut ctx, ak_handle_from_env()?)
+}
+
+/// Load an ESYS object for the persistent AK at `ak_handle`.
+fn ak_object(ctx: &mut TpmContext, ak_handle: u32) -> Result<ObjectHandle> {
+    let tpm_handle: TpmHandle = ak_handle
+        .try_into()
+        .context("invalid AK handle")?;
 
-    let decoded: DecodedKey = ak_public
+    ctx.execute_with_nullauth_session(|ctx| ctx.tr_from_tpm_public(tpm_handle))
+        .map_err(lockout::explain)
+        .context("AK not found at handle — was attestation-agent-init run?")
+}
+
+/// Read PCR `index` (0 to 23) from the `bank` (`sha1`, `sha256`, `sha384`
+/// or `sha512`) of the environment-resolved TPM.
+pub fn read_pcr(index: u8, bank: &str) -> Result<Vec<u8>> {
+    let algorithm = match bank {
+        "sha1" => HashingAlgorithm::Sha1,
+        "sha256" => HashingAlgorithm::Sha256,
+        "sha384" => HashingAlgorithm::Sha384,
+        "sha512" => HashingAlgorithm::Sha512,
+        _ => bail!("unsupported PCR bank {bank:?} (expected sha1, sha256, sha384 or sha512)"),
+    };
+    if index > 23 {
+        bail!("PCR {index} is out of range (expected 0..=23)");
+    }
+    let slot = PcrSlot::try_from(1u32 << index).context("invalid PCR slot")?;
+    let selection = PcrSelectionList::builder()
+        .with_selection(algorithm, &[slot])
+        .build()
+        .context("failed to build the PCR selection")?;
+
+    let mut ctx = create_context(tcti_from_env()?)?;
+    let (_, _, digests) = ctx
+        .pcr_read(selection)
+        .with_context(|| format!("failed to read PCR {index}"))?;
+    let digest = digests
+        .value()
+        .first()
+        .with_context(|| format!("TPM has no {bank} bank for PCR {index}"))?;
+    Ok(digest.value().to_vec())
+}
+
+/// The AK public key as the CoCo attestation-agent reports it in TPM
+/// evidence: a JSON fragment `{"ak_public": "<PEM>"}`, where the PEM
+/// `PUBLIC KEY` wraps the same SPKI DER this provider uses as IKM.
+///
+/// Comparing it with the `ak_public` of real evidence shows whether the
+/// provider and the agent agree on the AK, the usual cause of derivation
+/// mismatches. Holds public material only.
+pub fn coco_ak_public_field() -> Result<String> {
+    let ak_handle = ak_handle_from_env()?;
+    let mut ctx = create_context(tcti_from_env()?)?;
+    let pem = spki_pem(&read_ak_der(&mut ctx, ak_handle)?);
+    let value = serde_json::to_string(&pem).context("failed to encode ak_public")?;
+    Ok(format!("{{\"ak_public\": {value}}}"))
+}
+
+/// PEM-armor SPKI DER as a `PUBLIC KEY` with 64-character lines.
+fn spki_pem(der: &[u8]) -> String {
+    let body = B64.encode(der);
+    let mut pem = String::from("-----BEGIN PUBLIC KEY-----\n");
+    for line in body.as_bytes().chunks(64) {
+        pem.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+        pem.push('\n');
+    }
+    pem.push_str("-----END PUBLIC KEY-----\n");
+    pem
+}
+
+/// Encode an RSA TPM public area as DER SubjectPublicKeyInfo.
+///
+/// This is the exact byte representation returned by the TPM path, so it can
+/// be used offline (e.g. by verifier tooling) to precompute the IKM for a
+/// known AK public key without TPM access.
+pub fn spki_der_from_public(key: &Public) -> Result<Vec<u8>> {
+    let decoded: DecodedKey = key
+        .clone()
         .try_into()
         .context("failed to decode AK public key")?;
 
@@ -71,9 +710,85 @@ fn ak_public_key_der(tpm_device: &str) -> Result<Zeroizing<Vec<u8>>> {
         rsa_pk.modulus,
         rsa_pk.public_exponent,
     );
-    let der = picky_asn1_der::to_vec(&spki)
-        .context("failed to DER-encode AK public key")?;
+    picky_asn1_der::to_vec(&spki).context("failed to DER-encode AK public key")
+}
+
+#[cfg(all(test, feature = "test-fakes"))]
+mod tests {
+    use super::fakes::{self, FakeAkReader};
+    use super::*;
+
+    fn provider(reader: FakeAkReader) -> TpmSeedProvider {
+        TpmSeedProvider::default().with_ak_reader(Box::new(reader))
+    }
+
+    fn ak_public(byte: u8) -> Public {
+        fakes::rsa_ak_public(&[byte; 256]).unwrap()
+    }
+
+    fn ikm_error(provider: TpmSeedProvider) -> String {
+        format!("{:#}", provider.ikm().unwrap_err())
+    }
+
+    #[test]
+    fn ikm_is_the_ak_spki_der() {
+        let public = ak_public(0xa5);
+        let ikm = provider(FakeAkReader::Public(public.clone())).ikm().unwrap();
+        assert_eq!(&ikm[..], spki_der_from_public(&public).unwrap());
+    }
 
-    log::info!("read AK public key from handle {:#X} ({} bytes DER)", AK_HANDLE, der.len());
-    Ok(Zeroizing::new(der))
+    #[test]
+    fn missing_ak_is_reported() {
+        let err = ikm_error(provider(FakeAkReader::NotFound));
+        assert!(err.contains("AK not found at handle"), "{err}");
+    }
+
+    #[test]
+    fn non_rsa_ak_is_rejected() {
+        let err = ikm_error(provider(FakeAkReader::NotRsa));
+        assert!(err.contains("AK is not an RSA key"), "{err}");
+    }
+
+    #[test]
+    fn transient_tpm_error_is_propagated() {
+        let err = ikm_error(provider(FakeAkReader::Transient));
+        assert!(err.contains("failed to read AK public key"), "{err}");
+        assert!(err.contains("TPM_RC_RETRY"), "{err}");
+    }
+
+    #[test]
+    fn double_read_rejects_a_changing_ak() {
+        let reader = FakeAkReader::sequence(vec![ak_public(0xa5), ak_public(0x5a)]);
+        let err = ikm_error(provider(reader).with_double_read(true));
+        assert!(err.contains("AK public key changed between consecutive reads"), "{err}");
+    }
+
+    #[test]
+    fn double_read_accepts_a_stable_ak() {
+        let reader = FakeAkReader::sequence(vec![ak_public(0xa5), ak_public(0xa5)]);
+        assert!(provider(reader).with_double_read(true).ikm().is_ok());
+    }
+
+    #[test]
+    fn fresh_ikm_replaces_a_stale_public_cache() {
+        let dir = std::env::temp_dir().join(format!("fresh-ikm-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cache = dir.join("ak.pub");
+        let (old, new) = (ak_public(0x5a), ak_public(0xa5));
+        public_cache::store(&cache, AK_HANDLE, &old).unwrap();
+        let provider = provider(FakeAkReader::Public(new.clone())).with_public_cache(&cache);
+
+        let der = |public: &Public| spki_der_from_public(public).unwrap();
+        assert_eq!(provider.ikm().unwrap()[..], der(&old));
+        assert_eq!(provider.fresh_ikm().unwrap()[..], der(&new));
+        assert_eq!(public_cache::load(&cache, AK_HANDLE), Some(new.clone()));
+        assert_eq!(provider.ikm().unwrap()[..], der(&new));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tpm_only_inputs_need_a_tpm() {
+        let provider = provider(FakeAkReader::Public(ak_public(0xa5))).with_firmware_version(true);
+        assert!(ikm_error(provider).contains("has no TPM"));
+    }
 }
@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use tss_esapi::attributes::NvIndexAttributesBuilder;
+use tss_esapi::constants::NvIndexType;
+use tss_esapi::handles::{NvIndexHandle, NvIndexTpmHandle};
+use tss_esapi::interface_types::algorithm::HashingAlgorithm;
+use tss_esapi::interface_types::resource_handles::{NvAuth, Provision};
+use tss_esapi::structures::NvPublicBuilder;
+use tss_esapi::Context as TpmContext;
+
+use super::lockout;
+
+/// Size of a TPM_NT_COUNTER index: a big-endian u64.
+const COUNTER_SIZE: u16 = 8;
+
+/// Read the current value of the NV counter at `index`.
+pub fn read(ctx: &mut TpmContext, index: u32) -> Result<u64> {
+    let nv_handle = index_handle(ctx, index)?;
+    read_handle(ctx, index, nv_handle)
+}
+
+/// Increment the NV counter at `index`, defining it first (under the owner
+/// hierarchy, with empty auth) if it does not exist yet, and return the new
+/// value.
+///
+/// A TPM counter can never go back: even an undefined and redefined counter
+/// resumes from the highest value the TPM has seen. Every identity derived
+/// with the counter mixed in changes permanently.
+pub fn increment(ctx: &mut TpmContext, index: u32) -> Result<u64> {
+    // Only an absent index is defined; any other failure (lockout, auth,
+    // TCTI) is the caller's to see.
+    let nv_handle = if super::handle_resident(ctx, index)? {
+        index_handle(ctx, index)?
+    } else {
+        define(ctx, index)?
+    };
+
+    ctx.execute_with_nullauth_session(|ctx| ctx.nv_increment(NvAuth::NvIndex(nv_handle), nv_handle))
+        .map_err(lockout::explain)
+        .with_context(|| format!("failed to increment NV counter {index:#X}"))?;
+
+    read_handle(ctx, index, nv_handle)
+}
+
+fn index_handle(ctx: &mut TpmContext, index: u32) -> Result<NvIndexHandle> {
+    let tpm_handle = NvIndexTpmHandle::new(index)
+        .with_context(|| format!("{index:#X} is not an NV index handle"))?;
+    let object = ctx
+        .execute_without_session(|ctx| ctx.tr_from_tpm_public(tpm_handle.into()))
+        .map_err(lockout::explain)
+        .with_context(|| format!("NV counter {index:#X} is not defined"))?;
+    Ok(object.into())
+}
+
+fn read_handle(ctx: &mut TpmContext, index: u32, nv_handle: NvIndexHandle) -> Result<u64> {
+    let data = ctx
+        .execute_with_nullauth_session(|ctx| {
+            ctx.nv_read(NvAuth::NvIndex(nv_handle), nv_handle, COUNTER_SIZE, 0)
+        })
+        .map_err(lockout::explain)
+        .with_context(|| format!("failed to read NV counter {index:#X}"))?;
+    let value: [u8; 8] = data
+        .value()
+        .try_into()
+        .with_context(|| format!("NV counter {index:#X} is not {COUNTER_SIZE} bytes"))?;
+    Ok(u64::from_be_bytes(value))
+}
+
+fn define(ctx: &mut TpmContext, index: u32) -> Result<NvIndexHandle> {
+    let attributes = NvIndexAttributesBuilder::new()
+        .with_nv_index_type(NvIndexType::Counter)
+        .with_auth_read(true)
+        .with_auth_write(true)
+        .with_no_da(true)
+        .build()
+        .context("failed to build NV counter attributes")?;
+    let public = NvPublicBuilder::new()
+        .with_nv_index(NvIndexTpmHandle::new(index)?)
+        .with_index_name_algorithm(HashingAlgorithm::Sha256)
+        .with_index_attributes(attributes)
+        .with_data_area_size(COUNTER_SIZE.into())
+        .build()
+        .context("failed to build NV counter public area")?;
+
+    log::info!("defining NV counter {index:#X}");
+    ctx.execute_with_nullauth_session(|ctx| ctx.nv_define_space(Provision::Owner, None, public))
+        .map_err(lockout::explain)
+        .with_context(|| format!("failed to define NV counter {index:#X}"))
+}
@@ -0,0 +1,227 @@
+use anyhow::{bail, Context, Result};
+use tss_esapi::attributes::SessionAttributesBuilder;
+use tss_esapi::constants::SessionType;
+use tss_esapi::handles::{AuthHandle, ObjectHandle, SessionHandle, TpmHandle};
+use tss_esapi::interface_types::algorithm::HashingAlgorithm;
+use tss_esapi::interface_types::dynamic_handles::Persistent;
+use tss_esapi::interface_types::resource_handles::{Hierarchy, Provision};
+use tss_esapi::interface_types::session_handles::PolicySession;
+use tss_esapi::structures::{Auth, SymmetricDefinition};
+use tss_esapi::tcti_ldr::TctiNameConf;
+use tss_esapi::{Context as TpmContext, WrapperErrorKind};
+
+use super::templates::{ak_rsa_template, ek_rsa_tcg_template, ek_rsa_template};
+use super::{ak_handle_from_env, lockout, OWNER_PERSISTENT_RANGE, PLATFORM_PERSISTENT_RANGE};
+
+const HIERARCHY_ENV: &str = "AAI_PROVISION_HIERARCHY";
+const HIERARCHY_AUTH_ENV: &str = "AAI_HIERARCHY_AUTH";
+const EK_TEMPLATE_ENV: &str = "AAI_EK_TEMPLATE";
+
+/// Which template the transient EK is created from, from `AAI_EK_TEMPLATE`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EkTemplate {
+    /// `simplified` (default): user_with_auth, usable with null auth sessions.
+    Simplified,
+    /// `tcg`: the TCG default template, whose name matches the EK certificate.
+    Tcg,
+}
+
+/// Where and how the AK is provisioned.
+pub struct ProvisionOptions {
+    /// Persistent handle the AK is stored at.
+    pub ak_handle: u32,
+    /// Hierarchy the AK is persisted under.
+    pub hierarchy: Provision,
+    /// Auth value of `hierarchy`; empty when `None`.
+    pub hierarchy_auth: Option<String>,
+    pub ek_template: EkTemplate,
+}
+
+impl ProvisionOptions {
+    /// Options from the environment, shared by attestation-agent-init and
+    /// the provider's auto-provisioning so both create the same AK:
+    ///
+    /// - `TPM_AK_HANDLE`: the persistent handle (default 0x81010002).
+    /// - `AAI_PROVISION_HIERARCHY`: `owner` (default) persists in the owner
+    ///   range; the owner can later evict the key, and it is removed by
+    ///   TPM2_Clear. `platform` persists in the platform range, meant for
+    ///   firmware/OEM flows: only platform authorization (usually held by
+    ///   firmware) can evict it, and it survives TPM2_Clear.
+    /// - `AAI_HIERARCHY_AUTH`: the hierarchy's auth value for the eviction;
+    ///   otherwise the hierarchy auth is assumed empty.
+    /// - `AAI_EK_TEMPLATE=tcg`: create the transient EK from the TCG default
+    ///   template instead of the simplified one, so its name matches the EK
+    ///   certificate for flows that validate the EK cert chain.
+    pub fn from_env() -> Result<Self> {
+        let ak_handle = ak_handle_from_env()?;
+        let hierarchy = match std::env::var(HIERARCHY_ENV).as_deref() {
+            Err(_) | Ok("owner") => Provision::Owner,
+            Ok("platform") => Provision::Platform,
+            Ok(other) => {
+                bail!("unsupported {HIERARCHY_ENV} {other:?} (expected owner or platform)")
+            }
+        };
+        let ek_template = match std::env::var(EK_TEMPLATE_ENV).as_deref() {
+            Err(_) | Ok("simplified") => EkTemplate::Simplified,
+            Ok("tcg") => EkTemplate::Tcg,
+            Ok(other) => {
+                bail!("unsupported {EK_TEMPLATE_ENV} {other:?} (expected simplified or tcg)")
+            }
+        };
+
+        let range = match hierarchy {
+            Provision::Owner => OWNER_PERSISTENT_RANGE,
+            Provision::Platform => PLATFORM_PERSISTENT_RANGE,
+        };
+        if !range.contains(&ak_handle) {
+            bail!(
+                "AK handle {ak_handle:#X} is outside the {hierarchy:?} persistent range \
+                 {:#X}..={:#X}; set TPM_AK_HANDLE accordingly",
+                range.start(),
+                range.end()
+            );
+        }
+
+        Ok(ProvisionOptions {
+            ak_handle,
+            hierarchy,
+            hierarchy_auth: std::env::var(HIERARCHY_AUTH_ENV).ok(),
+            ek_template,
+        })
+    }
+}
+
+/// Whether an object is resident at the persistent `ak_handle`.
+pub fn ak_present(ctx: &mut TpmContext, ak_handle: u32) -> Result<bool> {
+    let tpm_handle: TpmHandle = ak_handle.try_into().context("invalid AK handle")?;
+    Ok(ctx
+        .execute_with_nullauth_session(|ctx| ctx.tr_from_tpm_public(tpm_handle))
+        .is_ok())
+}
+
+/// Provision a TPM Attestation Key as described by `options`.
+///
+/// Idempotent: if the handle is already occupied, nothing is done and
+/// `false` is returned; `true` means a new AK was created.
+///
+/// Equivalent to:
+///   tpm2_createek -c ek.ctx -G rsa
+///   tpm2_createak -C ek.ctx -c ak.ctx -G rsa -g sha256 -s rsassa
+///   tpm2_evictcontrol -c ak.ctx 0x81010002
+pub fn provision_ak(tcti: TctiNameConf, options: &ProvisionOptions) -> Result<bool> {
+    let ak_handle = options.ak_handle;
+    let hierarchy = options.hierarchy;
+    let ek_kind = options.ek_template;
+    let mut ctx = super::create_context(tcti)?;
+
+    if let Some(auth) = &options.hierarchy_auth {
+        let hierarchy_handle = match hierarchy {
+            Provision::Owner => ObjectHandle::Owner,
+            Provision::Platform => ObjectHandle::Platform,
+        };
+        let auth = Auth::try_from(auth.clone().into_bytes())
+            .with_context(|| format!("{HIERARCHY_AUTH_ENV} is too long"))?;
+        ctx.tr_set_auth(hierarchy_handle, auth)
+            .context("failed to set hierarchy auth")?;
+    }
+
+    if ak_present(&mut ctx, ak_handle)? {
+        log::info!("AK already exists at handle {:#X}, nothing to do", ak_handle);
+        return Ok(false);
+    }
+
+    let ek_template = match ek_kind {
+        EkTemplate::Simplified => ek_rsa_template()?,
+        EkTemplate::Tcg => ek_rsa_tcg_template()?,
+    };
+    let ak_template = ak_rsa_template()?;
+
+    log::info!("provisioning RSA AK at handle {:#X} under {:?}", ak_handle, hierarchy);
+
+    let provisioned = ctx.execute_with_nullauth_session(|ctx| -> tss_esapi::Result<()> {
+        // Create transient EK
+        let ek = ctx.create_primary(Hierarchy::Endorsement, ek_template, None, None, None, None)?;
+        log::info!("created transient EK ({ek_kind:?} template)");
+
+        // Create AK under EK
+        let ak = with_ek_auth(ctx, ek_kind, |ctx| {
+            ctx.create(ek.key_handle, ak_template, None, None, None, None)
+        })?;
+        log::info!("created AK key pair");
+
+        // Load AK into TPM
+        let ak_object = with_ek_auth(ctx, ek_kind, |ctx| {
+            ctx.load(ek.key_handle, ak.out_private, ak.out_public)
+        })?;
+        log::info!("loaded AK");
+
+        // Persist AK at target handle
+        let persistent = tss_esapi::handles::PersistentTpmHandle::new(ak_handle)?;
+        ctx.evict_control(hierarchy, ak_object.into(), Persistent::Persistent(persistent))?;
+        log::info!("persisted AK at handle {:#X}", ak_handle);
+
+        // Flush transient EK (AK transient handle consumed by evict_control)
+        ctx.flush_context(ek.key_handle.into())?;
+
+        Ok(())
+    });
+
+    if let Err(err) = &provisioned
+        && lockout::is_lockout(err)
+    {
+        match lockout::lockout_parameters(&mut ctx) {
+            Ok(params) => log::error!("TPM dictionary-attack state: {params}"),
+            Err(err) => log::warn!("could not read TPM lockout parameters: {err:#}"),
+        }
+    }
+    provisioned
+        .map_err(lockout::explain)
+        .context("TPM AK provisioning failed")?;
+
+    log::info!("AK provisioning complete");
+    Ok(true)
+}
+
+/// Run `f` with the authorization the EK needs as a parent: the current
+/// (null auth) session for the simplified template, or a fresh policy
+/// session satisfying PolicySecret(TPM_RH_ENDORSEMENT) for the TCG one. The
+/// policy session is flushed afterwards.
+fn with_ek_auth<T>(
+    ctx: &mut TpmContext,
+    template: EkTemplate,
+    f: impl FnOnce(&mut TpmContext) -> tss_esapi::Result<T>,
+) -> tss_esapi::Result<T> {
+    if template == EkTemplate::Simplified {
+        return f(ctx);
+    }
+
+    let session = ctx
+        .start_auth_session(
+            None,
+            None,
+            None,
+            SessionType::Policy,
+            SymmetricDefinition::AES_128_CFB,
+            HashingAlgorithm::Sha256,
+        )?
+        .ok_or(tss_esapi::Error::WrapperError(WrapperErrorKind::WrongValueFromTpm))?;
+    let (attributes, mask) = SessionAttributesBuilder::new()
+        .with_decrypt(true)
+        .with_encrypt(true)
+        .build();
+    ctx.tr_sess_set_attributes(session, attributes, mask)?;
+
+    ctx.execute_with_temporary_object(SessionHandle::from(session).into(), |ctx, _| {
+        ctx.execute_with_nullauth_session(|ctx| {
+            ctx.policy_secret(
+                PolicySession::try_from(session)?,
+                AuthHandle::Endorsement,
+                Default::default(),
+                Default::default(),
+                Default::default(),
+                None,
+            )
+        })?;
+        ctx.execute_with_session(Some(session), f)
+    })
+}
@@ -0,0 +1,71 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use tss_esapi::structures::Public;
+use tss_esapi::traits::{Marshall, UnMarshall};
+
+/// Load the AK public area cached at `path` for `ak_handle`.
+///
+/// The file is the big-endian handle followed by the marshalled TPMT_PUBLIC.
+/// A missing, unreadable or malformed cache, or one written for another
+/// handle, yields `None` so the caller falls back to the TPM.
+pub fn load(path: &Path, ak_handle: u32) -> Option<Public> {
+    let cached = fs::read(path).ok()?;
+    let (handle, public) = cached.split_first_chunk::<4>()?;
+    if u32::from_be_bytes(*handle) != ak_handle {
+        log::warn!("AK public cache {} is for another handle, ignoring it", path.display());
+        return None;
+    }
+    match Public::unmarshall(public) {
+        Ok(public) => Some(public),
+        Err(e) => {
+            log::warn!("AK public cache {} is malformed ({e}), ignoring it", path.display());
+            None
+        }
+    }
+}
+
+/// Cache `public` for `ak_handle` at `path`. The AK public area is neither
+/// secret nor mutable, so the file is world-readable (0644). It is written
+/// to a temporary file and renamed into place, so readers never see a
+/// partial cache. The temporary file is created exclusively and without
+/// following symlinks, so nothing planted at its predictable name is
+/// written through; a leftover is unlinked and creation retried once.
+pub fn store(path: &Path, ak_handle: u32, public: &Public) -> Result<()> {
+    let mut cached = ak_handle.to_be_bytes().to_vec();
+    cached.extend(public.marshall().context("failed to marshall AK public area")?);
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = Path::new(&tmp);
+    let open = || {
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .custom_flags(libc::O_NOFOLLOW)
+            .mode(0o644)
+            .open(tmp)
+    };
+    let file = match open() {
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            fs::remove_file(tmp).with_context(|| format!("failed to remove {}", tmp.display()))?;
+            open()
+        }
+        file => file,
+    };
+    let mut file = file.with_context(|| format!("failed to create {}", tmp.display()))?;
+    file.write_all(&cached)
+        .and_then(|()| file.sync_all())
+        .with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(tmp, path)
+        .with_context(|| format!("failed to move AK public cache into {}", path.display()))
+}
+
+/// Remove a stale cache, e.g. once the AK handle is found empty.
+pub fn invalidate(path: &Path) {
+    if fs::remove_file(path).is_ok() {
+        log::warn!("AK handle is empty; removed stale AK public cache {}", path.display());
+    }
+}
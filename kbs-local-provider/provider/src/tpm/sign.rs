@@ -0,0 +1,67 @@
+use anyhow::{Context, Result, bail};
+use tss_esapi::interface_types::algorithm::HashingAlgorithm;
+use tss_esapi::interface_types::resource_handles::Hierarchy;
+use tss_esapi::structures::{MaxBuffer, Signature, SignatureScheme};
+
+use super::{ak_handle_from_env, ak_object, create_context, lockout, tcti_from_env};
+
+/// Largest message TPM2_Hash accepts in one call (TPM2B_MAX_BUFFER).
+pub const MAX_SIGNED_DATA: usize = 1024;
+
+/// TPM_GENERATED_VALUE, the magic leading every TPM-produced attestation
+/// structure. The TPM refuses a hash ticket for data starting with it.
+const TPM_GENERATED_VALUE: [u8; 4] = [0xff, 0x54, 0x43, 0x47];
+
+/// Prefix of the proof-of-possession statement signed by [`prove_possession`].
+pub const POSSESSION_CONTEXT: &[u8] = b"kbs-local-provider ed25519 possession v1\0";
+
+/// Sign `data` with the AK (RSASSA-PKCS1-v1_5, SHA-256) and return the raw
+/// RSA signature, verifiable against the AK public key.
+///
+/// The AK is a restricted signing key, so the TPM only signs a digest it
+/// computed itself: `data` is hashed with TPM2_Hash, whose ticket vouches
+/// that it is not a forged TPM attestation structure, and TPM2_Sign then
+/// signs that digest. Hence `data` must be at most [`MAX_SIGNED_DATA`] bytes
+/// and must not start with TPM_GENERATED_VALUE (`0xff 'T' 'C' 'G'`).
+pub fn sign_with_ak(data: &[u8]) -> Result<Vec<u8>> {
+    if data.starts_with(&TPM_GENERATED_VALUE) {
+        bail!("data starts with TPM_GENERATED_VALUE; a restricted AK will not sign it");
+    }
+    let buffer = MaxBuffer::try_from(data.to_vec()).with_context(|| {
+        format!("{} bytes is over the {MAX_SIGNED_DATA}-byte TPM2_Hash limit", data.len())
+    })?;
+
+    let ak_handle = ak_handle_from_env()?;
+    let mut ctx = create_context(tcti_from_env()?)?;
+    let ak_obj = ak_object(&mut ctx, ak_handle)?;
+
+    let (digest, ticket) = ctx
+        .execute_without_session(|ctx| ctx.hash(buffer, HashingAlgorithm::Sha256, Hierarchy::Owner))
+        .context("TPM2_Hash failed")?;
+    let signature = ctx
+        .execute_with_nullauth_session(|ctx| {
+            ctx.sign(ak_obj.into(), digest, SignatureScheme::Null, ticket)
+        })
+        .map_err(lockout::explain)
+        .context("AK failed to sign")?;
+
+    match signature {
+        Signature::RsaSsa(rsa) => Ok(rsa.signature().value().to_vec()),
+        _ => bail!("AK returned an unexpected signature scheme"),
+    }
+}
+
+/// Have the AK sign a statement naming a derived Ed25519 public key.
+///
+/// The statement is [`POSSESSION_CONTEXT`] || `public_key` || `nonce`, where
+/// the nonce comes from the verifier to rule out replay. A verifier rebuilds
+/// it and checks the returned signature against the AK public key (the same
+/// SPKI the evidence reports). That shows whoever could use this AK signed
+/// this public key, after the nonce was issued; it says nothing about how
+/// the key was derived.
+pub fn prove_possession(public_key: &[u8; 32], nonce: &[u8]) -> Result<Vec<u8>> {
+    let mut statement = POSSESSION_CONTEXT.to_vec();
+    statement.extend_from_slice(public_key);
+    statement.extend_from_slice(nonce);
+    sign_with_ak(&statement).context("failed to sign the proof-of-possession statement")
+}
@@ -0,0 +1,232 @@
+use anyhow::{Context, Result, bail};
+use tss_esapi::attributes::ObjectAttributesBuilder;
+use tss_esapi::interface_types::algorithm::{HashingAlgorithm, PublicAlgorithm};
+use tss_esapi::structures::{
+    Digest, HashScheme, Public, PublicBuilder, PublicKeyRsa, PublicRsaParametersBuilder,
+    RsaExponent, RsaScheme, SymmetricDefinitionObject,
+};
+
+/// Persistent handle the AK is provisioned at and read from.
+pub const AK_HANDLE: u32 = 0x81010002;
+
+const AK_TEMPLATE_CHECK_ENV: &str = "TPM_AK_TEMPLATE_CHECK";
+
+/// RSA 2048 Endorsement Key template used as transient parent for AK creation.
+///
+/// Restricted decrypt key under the Endorsement hierarchy with AES-128-CFB
+/// symmetric protection. Uses user_with_auth (not admin_with_policy) so that
+/// null auth sessions work for create/load — the EK is transient and flushed
+/// immediately after AK provisioning.
+pub fn ek_rsa_template() -> Result<Public> {
+    let object_attributes = ObjectAttributesBuilder::new()
+        .with_restricted(true)
+        .with_decrypt(true)
+        .with_fixed_tpm(true)
+        .with_fixed_parent(true)
+        .with_sensitive_data_origin(true)
+        .with_user_with_auth(true)
+        .build()?;
+
+    let rsa_params = PublicRsaParametersBuilder::new()
+        .with_scheme(RsaScheme::Null)
+        .with_key_bits(tss_esapi::interface_types::key_bits::RsaKeyBits::Rsa2048)
+        .with_exponent(RsaExponent::default())
+        .with_symmetric(SymmetricDefinitionObject::Aes {
+            key_bits: tss_esapi::interface_types::key_bits::AesKeyBits::Aes128,
+            mode: tss_esapi::interface_types::algorithm::SymmetricMode::Cfb,
+        })
+        .with_restricted(true)
+        .with_is_signing_key(false)
+        .with_is_decryption_key(true)
+        .build()?;
+
+    // Zero-filled unique field for deterministic EK
+    let unique = PublicKeyRsa::new_empty_with_size(
+        tss_esapi::interface_types::key_bits::RsaKeyBits::Rsa2048,
+    );
+
+    PublicBuilder::new()
+        .with_public_algorithm(PublicAlgorithm::Rsa)
+        .with_name_hashing_algorithm(HashingAlgorithm::Sha256)
+        .with_object_attributes(object_attributes)
+        .with_rsa_parameters(rsa_params)
+        .with_rsa_unique_identifier(unique)
+        .build()
+        .context("failed to build EK RSA template")
+}
+
+/// TCG default EK authPolicy (PolicyA, SHA-256): PolicySecret(TPM_RH_ENDORSEMENT).
+///
+/// Source: TCG EK Credential Profile for TPM Family 2.0, Appendix B.3.3.
+const TCG_EK_AUTH_POLICY_SHA256: [u8; 32] = [
+    0x83, 0x71, 0x97, 0x67, 0x44, 0x84, 0xb3, 0xf8, 0x1a, 0x90, 0xcc, 0x8d, 0x46, 0xa5, 0xd7, 0x24,
+    0xfd, 0x52, 0xd7, 0x6e, 0x06, 0x52, 0x0b, 0x64, 0xf2, 0xa1, 0xda, 0x1b, 0x33, 0x14, 0x69, 0xaa,
+];
+
+/// TCG default RSA 2048 EK template (EK Credential Profile template L-1).
+///
+/// Unlike [`ek_rsa_template`], this sets admin_with_policy instead of
+/// user_with_auth and carries the standard authPolicy. The public area, and
+/// therefore the EK name, then matches the manufacturer's EK certificate, so
+/// the EK can be validated against its cert chain. The price is that every
+/// use of the EK as a parent needs a policy session satisfying
+/// PolicySecret(TPM_RH_ENDORSEMENT) instead of a null auth session.
+pub fn ek_rsa_tcg_template() -> Result<Public> {
+    let object_attributes = ObjectAttributesBuilder::new()
+        .with_restricted(true)
+        .with_decrypt(true)
+        .with_fixed_tpm(true)
+        .with_fixed_parent(true)
+        .with_sensitive_data_origin(true)
+        .with_admin_with_policy(true)
+        .build()?;
+
+    let rsa_params = PublicRsaParametersBuilder::new()
+        .with_scheme(RsaScheme::Null)
+        .with_key_bits(tss_esapi::interface_types::key_bits::RsaKeyBits::Rsa2048)
+        .with_exponent(RsaExponent::default())
+        .with_symmetric(SymmetricDefinitionObject::AES_128_CFB)
+        .with_restricted(true)
+        .with_is_signing_key(false)
+        .with_is_decryption_key(true)
+        .build()?;
+
+    let unique = PublicKeyRsa::new_empty_with_size(
+        tss_esapi::interface_types::key_bits::RsaKeyBits::Rsa2048,
+    );
+
+    PublicBuilder::new()
+        .with_public_algorithm(PublicAlgorithm::Rsa)
+        .with_name_hashing_algorithm(HashingAlgorithm::Sha256)
+        .with_object_attributes(object_attributes)
+        .with_auth_policy(Digest::try_from(TCG_EK_AUTH_POLICY_SHA256.as_slice())?)
+        .with_rsa_parameters(rsa_params)
+        .with_rsa_unique_identifier(unique)
+        .build()
+        .context("failed to build TCG EK RSA template")
+}
+
+/// RSA 2048 Attestation Key template (matches `tpm2_createak -G rsa -g sha256 -s rsassa`).
+///
+/// Signing key with RSASSA-SHA256 scheme, created under the EK.
+pub fn ak_rsa_template() -> Result<Public> {
+    let object_attributes = ObjectAttributesBuilder::new()
+        .with_restricted(true)
+        .with_sign_encrypt(true)
+        .with_fixed_tpm(true)
+        .with_fixed_parent(true)
+        .with_sensitive_data_origin(true)
+        .with_user_with_auth(true)
+        .build()?;
+
+    let rsa_params = PublicRsaParametersBuilder::new()
+        .with_scheme(RsaScheme::RsaSsa(HashScheme::new(HashingAlgorithm::Sha256)))
+        .with_key_bits(tss_esapi::interface_types::key_bits::RsaKeyBits::Rsa2048)
+        .with_exponent(RsaExponent::default())
+        .with_restricted(true)
+        .with_is_signing_key(true)
+        .with_is_decryption_key(false)
+        .build()?;
+
+    PublicBuilder::new()
+        .with_public_algorithm(PublicAlgorithm::Rsa)
+        .with_name_hashing_algorithm(HashingAlgorithm::Sha256)
+        .with_object_attributes(object_attributes)
+        .with_rsa_parameters(rsa_params)
+        .with_rsa_unique_identifier(PublicKeyRsa::default())
+        .build()
+        .context("failed to build AK RSA template")
+}
+
+/// What to do when the key at the AK handle does not look like the AK this
+/// crate provisions, from `TPM_AK_TEMPLATE_CHECK` (`off`, `warn` or
+/// `strict`; default `warn`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AkTemplateCheck {
+    Off,
+    #[default]
+    Warn,
+    Strict,
+}
+
+impl AkTemplateCheck {
+    pub fn from_env() -> Result<Self> {
+        match std::env::var(AK_TEMPLATE_CHECK_ENV).as_deref() {
+            Err(_) | Ok("warn") => Ok(Self::Warn),
+            Ok("off") => Ok(Self::Off),
+            Ok("strict") => Ok(Self::Strict),
+            Ok(other) => bail!(
+                "unsupported {AK_TEMPLATE_CHECK_ENV} {other:?} (expected off, warn or strict)"
+            ),
+        }
+    }
+}
+
+/// The properties in which `public` differs from [`ak_rsa_template`]:
+/// algorithm, name algorithm, object attributes, auth policy and RSA
+/// parameters (key size, scheme, exponent). Empty means it matches.
+///
+/// The hierarchy a key was created under is not part of its public area, so
+/// it cannot be read back. A key made from this template is at least fixed
+/// to its TPM and parent and restricted to signing, which rules out the
+/// usual wrong keys at the handle (an SRK, an unrestricted or imported key,
+/// a different algorithm); an identical key created under the Owner SRK
+/// would still pass.
+pub fn ak_template_mismatches(public: &Public) -> Result<Vec<&'static str>> {
+    let Public::Rsa {
+        object_attributes: expected_attributes,
+        name_hashing_algorithm: expected_name_alg,
+        auth_policy: expected_policy,
+        parameters: expected_parameters,
+        ..
+    } = ak_rsa_template()?
+    else {
+        unreachable!("the AK template is RSA");
+    };
+    let Public::Rsa {
+        object_attributes,
+        name_hashing_algorithm,
+        auth_policy,
+        parameters,
+        ..
+    } = public
+    else {
+        return Ok(vec!["algorithm (not RSA)"]);
+    };
+
+    let mut mismatches = Vec::new();
+    if *name_hashing_algorithm != expected_name_alg {
+        mismatches.push("name algorithm");
+    }
+    if *object_attributes != expected_attributes {
+        mismatches.push("object attributes");
+    }
+    if *auth_policy != expected_policy {
+        mismatches.push("auth policy");
+    }
+    if *parameters != expected_parameters {
+        mismatches.push("RSA parameters");
+    }
+    Ok(mismatches)
+}
+
+/// Apply `check` to the key read from the AK handle: log any mismatch with
+/// [`ak_rsa_template`], and with [`AkTemplateCheck::Strict`] refuse the key.
+pub fn check_ak_public(public: &Public, ak_handle: u32, check: AkTemplateCheck) -> Result<()> {
+    if check == AkTemplateCheck::Off {
+        return Ok(());
+    }
+    let mismatches = ak_template_mismatches(public)?;
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+    let message = format!(
+        "key at AK handle {ak_handle:#X} differs from the AK template in: {}",
+        mismatches.join(", ")
+    );
+    if check == AkTemplateCheck::Strict {
+        bail!("{message}; refusing to derive from it ({AK_TEMPLATE_CHECK_ENV}=strict)");
+    }
+    log::warn!("{message}; the derived identity may not be the intended AK's");
+    Ok(())
+}